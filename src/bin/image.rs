@@ -21,7 +21,7 @@ fn main() {
         let text: Vec<_> = detections.iter().map(|s| normalize_string(s)).collect();
         println!("{:#?}", text);
 
-        let db = Database::load_from_file(None, None);
+        let db = Database::load_from_file(None, None).unwrap();
         let items: Vec<_> = text.iter().map(|s| db.find_item(s, None)).collect();
         for item in items.iter() {
             if let Some(item) = item {
@@ -37,7 +37,7 @@ fn main() {
                     .unwrap_or_else(|| "ERROR".to_string())
             })
             .collect();
-        let theme = detect_theme(&image);
+        let theme = detect_theme(&image).into_best();
         labels.insert(
             filepath
                 .file_name()
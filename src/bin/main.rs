@@ -1,13 +1,19 @@
 use std::{
-    borrow::Borrow,
     error::Error,
     fs::File,
     io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::PathBuf,
     sync::mpsc,
     thread::sleep,
     time::Duration
 };
 
+use clap::Parser;
+use copypasta::{ClipboardContext, ClipboardProvider};
+use global_hotkey::{
+    hotkey::{Code, HotKey},
+    GlobalHotKeyEvent, GlobalHotKeyManager,
+};
 use glutin::{display::GetGlDisplay, prelude::{GlDisplay, NotCurrentGlContext, PossiblyCurrentGlContext}, surface::GlSurface};
 use image::DynamicImage;
 use imgui_winit_glow_renderer_viewports::Renderer;
@@ -15,9 +21,12 @@ use log::info;
 use notify::{watcher, RecursiveMode, Watcher};
 
 use wfinfo::{
-    database::Database,
-    ocr::{normalize_string, reward_image_to_reward_names},
-    utils::fetch_prices_and_items,
+    database::{Database, Reward, RewardKind, Valuation},
+    ocr::{relic_selection_image_to_relics, reward_image_to_rewards, RewardDetection, SquadRelic},
+    snap::{snap_items, Region},
+    theme::Theme,
+    trade_chat::{price_check, PriceCheck},
+    utils::{Config, DataArguments},
 };
 
 use std::{ffi::CString, num::NonZeroU32, time::Instant};
@@ -183,6 +192,22 @@ pub fn wayland_capture(impl_monitor: &xcap::Monitor) -> xcap::XCapResult<RgbaIma
     let width = ((impl_monitor.width() as f32) * impl_monitor.scale_factor()) as i32;
     let height = ((impl_monitor.height() as f32) * impl_monitor.scale_factor()) as i32;
 
+    portal_capture(x, y, width, height)
+}
+
+/// Captures the part of the screen covered by the window, through the portal like
+/// [`wayland_capture`].
+pub fn window_capture(window: &xcap::Window) -> xcap::XCapResult<RgbaImage> {
+    let scale_factor = window.current_monitor().scale_factor();
+    let x = ((window.x() as f32) * scale_factor) as i32;
+    let y = ((window.y() as f32) * scale_factor) as i32;
+    let width = ((window.width() as f32) * scale_factor) as i32;
+    let height = ((window.height() as f32) * scale_factor) as i32;
+
+    portal_capture(x, y, width, height)
+}
+
+fn portal_capture(x: i32, y: i32, width: i32, height: i32) -> xcap::XCapResult<RgbaImage> {
     let lock = DBUS_LOCK.lock();
 
     let conn = Connection::new_session()?;
@@ -193,31 +218,98 @@ pub fn wayland_capture(impl_monitor: &xcap::Monitor) -> xcap::XCapResult<RgbaIma
     res
 }
 
-fn run_detection(monitor: &xcap::Monitor, db: &Database) -> Vec<wfinfo::database::Item> {
-    let frame = wayland_capture(monitor).unwrap();
+/// What the screenshots are taken of.
+enum CaptureTarget {
+    /// The whole monitor
+    Monitor(xcap::Monitor),
+    /// The game window with this title or application name, looked up again for every capture
+    /// as it may have moved
+    Window(String),
+}
+
+impl CaptureTarget {
+    fn capture(&self) -> xcap::XCapResult<RgbaImage> {
+        match self {
+            CaptureTarget::Monitor(monitor) => wayland_capture(monitor),
+            CaptureTarget::Window(name) => {
+                let window = xcap::Window::all()?
+                    .into_iter()
+                    .find(|window| window.title() == name || window.app_name() == name)
+                    .ok_or_else(|| xcap::XCapError::new(format!("No window named {name}")))?;
+                window_capture(&window)
+            }
+        }
+    }
+}
+
+fn run_detection(
+    target: &CaptureTarget,
+    db: &Database,
+    theme: Option<Theme>,
+) -> RewardDetection {
+    let frame = target.capture().unwrap();
     info!("Captured");
     let image = DynamicImage::ImageRgba8(frame);
     info!("Converted");
-    let text = reward_image_to_reward_names(image, None);
-    let text = text.iter().map(|s| normalize_string(s));
-    println!("{:#?}", text);
-    let items: Vec<_> = text
-        .map(move |s| {
-            db.find_item(&s, None)
-                .unwrap_or(&wfinfo::database::Item {
-                    drop_name: ("".to_owned()),
-                    ducats: (0),
-                    name: ("".to_owned()),
-                    platinum: (0.0 as f32),
-                })
-                .to_owned()
-        })
+    // Empty slots and unreadable rewards are left out
+    let detection = reward_image_to_rewards(&image, theme, db);
+    println!(
+        "Read with the {} theme, {:.0}% matched",
+        detection.theme.name,
+        detection.confidence * 100.0
+    );
+    detection
+}
+
+fn run_relic_detection(
+    target: &CaptureTarget,
+    db: &Database,
+    theme: Option<Theme>,
+) -> Vec<SquadRelic> {
+    let frame = target.capture().unwrap();
+    info!("Captured");
+    let image = DynamicImage::ImageRgba8(frame);
+    let squad = relic_selection_image_to_relics(image, theme, &db.relics);
+    println!("{:#?}", squad);
+    squad
+}
+
+fn run_snap(
+    frame: &DynamicImage,
+    region: Region,
+    db: &Database,
+    theme: Option<Theme>,
+) -> Vec<wfinfo::database::Item> {
+    let items: Vec<_> = snap_items(frame, region, theme, db)
+        .into_iter()
+        .cloned()
         .collect();
+    println!("{:#?}", items);
+    items
+}
 
-    return items;
+#[derive(Parser, Debug)]
+/// Overlay showing the platinum and ducat values of relic rewards.
+struct Arguments {
+    /// Path to the game's EE.log file
+    ee_log: PathBuf,
+    /// Title or application name of the game window to capture, e.g. gamescope. The primary
+    /// monitor is captured when not given
+    #[arg(long)]
+    window_name: Option<String>,
+    /// Name of the UI theme, e.g. one saved with theme_tune. Defaults to the theme in the
+    /// config.json in the data directory, without one it's detected from every screenshot
+    #[arg(long)]
+    theme: Option<Theme>,
+    /// Price check item links and trade messages copied to the clipboard
+    #[arg(long)]
+    watch_clipboard: bool,
+    #[command(flatten)]
+    data: DataArguments,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let arguments = Arguments::parse();
     let event_loop = EventLoop::new().unwrap();
     let window_builder = winit::window::Window::default_attributes()
         .with_inner_size(LogicalSize::new(400.0, 200.0))
@@ -294,35 +386,88 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let mut last_frame = Instant::now();
 
-    let mut items: Vec<wfinfo::database::Item> = Vec::new();
+    let mut items: Vec<Reward> = Vec::new();
+    // Name of the theme the rewards were read with
+    let mut reward_theme = String::new();
+    let mut squad: Vec<SquadRelic> = Vec::new();
 
-    let path = std::env::args().nth(1).unwrap();
-    println!("Path: {}", path);
+    let path = arguments.ee_log;
+    println!("Path: {}", path.display());
     let (tx, rx) = mpsc::channel();
     let mut watcher = watcher(tx, Duration::from_millis(100)).unwrap();
     watcher
         .watch(&path, RecursiveMode::NonRecursive)
-        .unwrap_or_else(|_| panic!("Failed to open EE.log file: {path}"));
+        .unwrap_or_else(|_| panic!("Failed to open EE.log file: {}", path.display()));
 
     let mut position = File::open(&path).unwrap().seek(SeekFrom::End(0)).unwrap();
     println!("Position: {}", position);
 
-    let monitors = xcap::Monitor::all().unwrap();
+    let capture_target = match arguments.window_name {
+        Some(name) => CaptureTarget::Window(name),
+        None => CaptureTarget::Monitor(xcap::Monitor::all().unwrap().swap_remove(0)),
+    };
+
+    let db = arguments.data.load_database()?;
+    let theme = match arguments.theme {
+        Some(theme) => Some(theme),
+        None => Config::load(&Config::path())?.theme()?,
+    };
 
-    let (prices, dbitems) = fetch_prices_and_items()?;
-    let db = Database::load_from_file(Some(&prices), Some(&dbitems));
+    // Snap-it: the hotkey captures the screen, then a rectangle dragged in the overlay selects
+    // the items to look up
+    let hotkeys = GlobalHotKeyManager::new()?;
+    let snap_hotkey = HotKey::new(None, Code::F10);
+    hotkeys.register(snap_hotkey)?;
+    let mut snap_frame: Option<DynamicImage> = None;
+    let mut snap_start: Option<[f32; 2]> = None;
+    let mut snapped: Vec<wfinfo::database::Item> = Vec::new();
+
+    let mut clipboard = if arguments.watch_clipboard {
+        Some(ClipboardContext::new().map_err(|err| err.to_string())?)
+    } else {
+        None
+    };
+    let mut clipboard_contents = String::new();
+    let mut last_clipboard_poll = Instant::now();
+    let mut price_checks: Vec<PriceCheck> = Vec::new();
 
     let _ = event_loop.run(move |event, window_target | {
         window_target.set_control_flow(winit::event_loop::ControlFlow::Poll);
 
         renderer.handle_event(&mut imgui, &window, &event);
 
+        if let Ok(hotkey) = GlobalHotKeyEvent::receiver().try_recv() {
+            if hotkey.id == snap_hotkey.id() && snap_frame.is_none() {
+                println!("Snap-it: drag a rectangle around the items, escape cancels");
+                snapped.clear();
+                snap_frame = Some(DynamicImage::ImageRgba8(
+                    capture_target.capture().unwrap(),
+                ));
+                window.request_redraw();
+            }
+        }
+
+        if let Some(clipboard) = clipboard.as_mut() {
+            if last_clipboard_poll.elapsed() >= Duration::from_millis(500) {
+                last_clipboard_poll = Instant::now();
+                if let Ok(contents) = clipboard.get_contents() {
+                    if contents != clipboard_contents {
+                        price_checks = price_check(&db, &contents);
+                        println!("Price check: {:#?}", price_checks);
+                        clipboard_contents = contents;
+                        window.request_redraw();
+                    }
+                }
+            }
+        }
+
         match rx.try_recv() {
             Ok(notify::DebouncedEvent::Write(_)) => {
                 let mut f = File::open(&path).unwrap();
                 f.seek(SeekFrom::Start(position)).unwrap();
 
                 let mut reward_screen_detected = false;
+                let mut relic_selection_detected = false;
                 let mut end_of_match_detected = false;
 
                 let reader = BufReader::new(f.by_ref());
@@ -341,37 +486,58 @@ fn main() -> Result<(), Box<dyn Error>> {
                         println!("> {:?}", line);
                         reward_screen_detected = true;
                     }
+                    if line.contains("Created /Lotus/Interface/VoidProjectionSelection.swf") {
+                        println!("> {:?}", line);
+                        relic_selection_detected = true;
+                    }
                     if line.contains("Created /Lotus/Interface/EndOfMatch.swf") {
                         end_of_match_detected = true;
                     }
                 }
 
+                if relic_selection_detected {
+                    println!("Relic selection detected, waiting...");
+                    sleep(Duration::from_millis(1500));
+                    println!("Capturing");
+                    squad = run_relic_detection(&capture_target, &db, theme.clone());
+                    window.request_redraw();
+                }
+
                 if reward_screen_detected {
                     println!("Detected, waiting...");
                     sleep(Duration::from_millis(1500));
                     println!("Capturing");
                     let mut rewards = String::new();
 
-                    items = run_detection(monitors[0].borrow(), &db).clone();
+                    let detection = run_detection(&capture_target, &db, theme.clone());
+                    items = detection.rewards;
+                    reward_theme = detection.theme.name;
 
                     let best = items
                         .iter()
-                        .map(|item| {
-                            item.platinum
-                                .max(item.ducats as f32 / 10.0 + item.platinum / 100.0)
-                        })
                         .enumerate()
+                        .filter(|(_, reward)| reward.item.kind == RewardKind::PrimePart)
+                        .map(|(index, reward)| {
+                            let item = &reward.item;
+                            (
+                                index,
+                                item.platinum
+                                    .max(item.ducats as f32 / 10.0 + item.platinum / 100.0),
+                            )
+                        })
                         .max_by(|a, b| a.1.total_cmp(&b.1))
                         .map(|b: (usize, f32)| b.0);
 
                     let mut result = String::new();
 
-                    for (index, item) in items.iter().enumerate() {
+                    for (index, reward) in items.iter().enumerate() {
+                        let item = &reward.item;
                         result.push_str(&format!(
-                            "{}\t{}\t{}\t{}\n",
-                            item.drop_name,
+                            "{}\t{}\t{}\t{}\t{}\n",
+                            reward,
                             item.platinum,
                             item.ducats as f32 / 10.0,
+                            if item.vaulted { "vaulted" } else { "" },
                             if Some(index) == best { "<----" } else { "" }
                         ));
                     }
@@ -383,6 +549,8 @@ fn main() -> Result<(), Box<dyn Error>> {
                 if end_of_match_detected {
                     println!("Match ended!");
                     items.clear();
+                    squad.clear();
+                    snapped.clear();
                     window.request_redraw();
                 }
 
@@ -420,6 +588,11 @@ fn main() -> Result<(), Box<dyn Error>> {
             winit::event::Event::AboutToWait => {
                 window.request_redraw();
                 window.set_maximized(false);
+                // The overlay only takes the mouse while a snap-it rectangle is being dragged
+                imgui
+                    .io_mut()
+                    .config_flags
+                    .set(ConfigFlags::NO_MOUSE, snap_frame.is_none());
                 let ui = imgui.frame();
                 if items.len() > 0 {
                     ui.window("RelicRewards")
@@ -435,26 +608,244 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .flags(WindowFlags::NO_INPUTS | WindowFlags::NO_NAV_FOCUS)
                         .nav_inputs(false)
                         .build(|| {
-                            ui.text("rewards:");
+                            ui.text(format!("rewards ({} theme):", reward_theme));
                             if let Some(_t) = ui.begin_table_header_with_flags(
                                 "Basic-Table",
                                 [
                                     TableColumnSetup::new("Name"),
                                     TableColumnSetup::new("Platinum"),
                                     TableColumnSetup::new("Ducats"),
+                                    TableColumnSetup::new("Vaulted"),
+                                    TableColumnSetup::new("Relics"),
                                 ],
                                 TableFlags::BORDERS | TableFlags::SIZING_FIXED_FIT,
                             ) {
-                                items.sort_by(|a, b| b.platinum.total_cmp(&a.platinum));
-                                for (_index, item) in items.iter().enumerate() {
+                                items.sort_by(|a, b| b.item.platinum.total_cmp(&a.item.platinum));
+                                for (_index, reward) in items.iter().enumerate() {
+                                    let item = &reward.item;
                                     ui.table_next_column();
-                                    ui.text(item.drop_name.clone());
+                                    ui.text(match item.kind {
+                                        RewardKind::PrimePart => item.drop_name.clone(),
+                                        _ => reward.to_string(),
+                                    });
+
+                                    // Only prime parts are traded
+                                    if item.kind != RewardKind::PrimePart {
+                                        ui.table_next_row();
+                                        continue;
+                                    }
 
                                     ui.table_next_column();
                                     ui.text(format!("{}", item.platinum));
 
                                     ui.table_next_column();
                                     ui.text(format!("{}", item.ducats));
+
+                                    ui.table_next_column();
+                                    ui.text(if item.vaulted { "Yes" } else { "No" });
+
+                                    ui.table_next_column();
+                                    let sources: Vec<_> = db
+                                        .relic_sources(&item.name)
+                                        .iter()
+                                        .map(|source| source.to_string())
+                                        .collect();
+                                    ui.text(sources.join(", "));
+                                    ui.table_next_row();
+                                }
+                                ui.new_line();
+                            }
+                        });
+                }
+
+                if !squad.is_empty() {
+                    ui.window("SquadRelics")
+                        .size([400.0, 200.0], Condition::FirstUseEver)
+                        .position([0.0, 220.0], Condition::FirstUseEver)
+                        .resizable(true)
+                        .focused(false)
+                        .nav_focus(false)
+                        .focus_on_appearing(false)
+                        .draw_background(false)
+                        .bring_to_front_on_focus(true)
+                        .bg_alpha(0.5)
+                        .flags(WindowFlags::NO_INPUTS | WindowFlags::NO_NAV_FOCUS)
+                        .nav_inputs(false)
+                        .build(|| {
+                            // Members whose relic isn't known are left out of both the value
+                            // and the table
+                            let members: Vec<_> = squad
+                                .iter()
+                                .filter_map(|member| {
+                                    let relic = db.relics.get(&member.relic)?;
+                                    Some((member, relic, member.refinement))
+                                })
+                                .collect();
+                            let cracked: Vec<_> = members
+                                .iter()
+                                .map(|(_, relic, refinement)| (*relic, *refinement))
+                                .collect();
+                            ui.text(format!(
+                                "squad relics, expected value: {:.1}",
+                                db.squad_relic_value_in(&cracked, Valuation::Platinum)
+                            ));
+                            if let Some(_t) = ui.begin_table_header_with_flags(
+                                "Squad-Table",
+                                [
+                                    TableColumnSetup::new("Relic"),
+                                    TableColumnSetup::new("Refinement"),
+                                    TableColumnSetup::new("Value"),
+                                    TableColumnSetup::new("Vaulted"),
+                                ],
+                                TableFlags::BORDERS | TableFlags::SIZING_FIXED_FIT,
+                            ) {
+                                for (member, relic, refinement) in members.iter() {
+                                    ui.table_next_column();
+                                    ui.text(member.relic.to_string());
+
+                                    ui.table_next_column();
+                                    ui.text(format!("{:?}", refinement));
+
+                                    ui.table_next_column();
+                                    ui.text(format!(
+                                        "{:.1}",
+                                        db.shared_relic_value(relic, *refinement, 1)
+                                    ));
+
+                                    ui.table_next_column();
+                                    ui.text(if relic.vaulted { "Yes" } else { "No" });
+                                    ui.table_next_row();
+                                }
+                                ui.new_line();
+                            }
+                        });
+                }
+
+                let mut snap_done = false;
+                if let Some(frame) = snap_frame.as_ref() {
+                    let mouse = ui.io().mouse_pos;
+                    let scale = ui.io().display_framebuffer_scale;
+                    let draw_list = ui.get_foreground_draw_list();
+                    match snap_start {
+                        _ if ui.is_key_pressed(Key::Escape) => snap_done = true,
+                        None => {
+                            draw_list.add_text(
+                                [mouse[0] + 16.0, mouse[1]],
+                                [1.0, 1.0, 1.0],
+                                "Drag around the items to look up",
+                            );
+                            if ui.is_mouse_clicked(MouseButton::Left) {
+                                snap_start = Some(mouse);
+                            }
+                        }
+                        Some(start) => {
+                            draw_list
+                                .add_rect(start, mouse, [1.0, 1.0, 0.0])
+                                .thickness(2.0)
+                                .build();
+                            if ui.is_mouse_released(MouseButton::Left) {
+                                let pixel = |point: [f32; 2]| {
+                                    (
+                                        (point[0] * scale[0]).max(0.0) as u32,
+                                        (point[1] * scale[1]).max(0.0) as u32,
+                                    )
+                                };
+                                let region = Region::from_corners(pixel(start), pixel(mouse));
+                                snapped = run_snap(frame, region, &db, theme.clone());
+                                snap_done = true;
+                            }
+                        }
+                    }
+                }
+                if snap_done {
+                    snap_frame = None;
+                    snap_start = None;
+                }
+
+                if !snapped.is_empty() {
+                    ui.window("SnapIt")
+                        .size([400.0, 200.0], Condition::FirstUseEver)
+                        .position([420.0, 0.0], Condition::FirstUseEver)
+                        .resizable(true)
+                        .focused(false)
+                        .nav_focus(false)
+                        .focus_on_appearing(false)
+                        .draw_background(false)
+                        .bring_to_front_on_focus(true)
+                        .bg_alpha(0.5)
+                        .flags(WindowFlags::NO_INPUTS | WindowFlags::NO_NAV_FOCUS)
+                        .nav_inputs(false)
+                        .build(|| {
+                            ui.text("snapped items:");
+                            if let Some(_t) = ui.begin_table_header_with_flags(
+                                "Snap-Table",
+                                [
+                                    TableColumnSetup::new("Name"),
+                                    TableColumnSetup::new("Platinum"),
+                                    TableColumnSetup::new("Ducats"),
+                                    TableColumnSetup::new("Vaulted"),
+                                ],
+                                TableFlags::BORDERS | TableFlags::SIZING_FIXED_FIT,
+                            ) {
+                                for item in snapped.iter() {
+                                    ui.table_next_column();
+                                    ui.text(item.name.clone());
+
+                                    ui.table_next_column();
+                                    ui.text(format!("{}", item.platinum));
+
+                                    ui.table_next_column();
+                                    ui.text(format!("{}", item.ducats));
+
+                                    ui.table_next_column();
+                                    ui.text(if item.vaulted { "Yes" } else { "No" });
+                                    ui.table_next_row();
+                                }
+                                ui.new_line();
+                            }
+                        });
+                }
+
+                if !price_checks.is_empty() {
+                    ui.window("PriceCheck")
+                        .size([400.0, 200.0], Condition::FirstUseEver)
+                        .position([420.0, 220.0], Condition::FirstUseEver)
+                        .resizable(true)
+                        .focused(false)
+                        .nav_focus(false)
+                        .focus_on_appearing(false)
+                        .draw_background(false)
+                        .bring_to_front_on_focus(true)
+                        .bg_alpha(0.5)
+                        .flags(WindowFlags::NO_INPUTS | WindowFlags::NO_NAV_FOCUS)
+                        .nav_inputs(false)
+                        .build(|| {
+                            ui.text("price check:");
+                            if let Some(_t) = ui.begin_table_header_with_flags(
+                                "Price-Check-Table",
+                                [
+                                    TableColumnSetup::new("Name"),
+                                    TableColumnSetup::new("Platinum"),
+                                    TableColumnSetup::new("Ducats"),
+                                    TableColumnSetup::new("Vaulted"),
+                                ],
+                                TableFlags::BORDERS | TableFlags::SIZING_FIXED_FIT,
+                            ) {
+                                for check in price_checks.iter() {
+                                    ui.table_next_column();
+                                    ui.text(check.name.clone());
+
+                                    ui.table_next_column();
+                                    ui.text(format!("{}", check.platinum));
+
+                                    ui.table_next_column();
+                                    ui.text(match check.ducats {
+                                        Some(ducats) => format!("{}", ducats),
+                                        None => "-".to_string(),
+                                    });
+
+                                    ui.table_next_column();
+                                    ui.text(if check.vaulted { "Yes" } else { "No" });
                                     ui.table_next_row();
                                 }
                                 ui.new_line();
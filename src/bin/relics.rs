@@ -1,99 +1,1918 @@
-use std::collections::HashMap;
+use std::{
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
 
+use anyhow::{anyhow, bail};
+use clap::{Parser, Subcommand, ValueEnum};
+use indexmap::IndexMap;
+use serde::Serialize;
 use wfinfo::{
-    database::Database,
-    wfinfo_data::item_data::{Refinement, Relic},
+    database::{Database, Valuation},
+    ducats::{plan_trade_ins, TradeInOptions, TradeInPlan},
+    history::{relic_value_changes, PriceHistory, SnapshotDate, ValueChange},
+    inventory::{
+        plan_cracks, plan_sales, CrackAdvice, Inventory, OwnedPart, OwnedRelic, PartChange,
+        SalePlan,
+    },
+    inventory_scan::scan_inventory,
+    snap::{snap_items, Region},
+    traces::{plan_trace_spending, refinement_options, RefinementOption, TracePlan},
+    trade_chat::{price_check, PriceCheck},
+    utils::DataArguments,
+    wfinfo_data::item_data::{Rarity, Refinement, Relic, RelicDrop, RelicId, RelicTier},
 };
 
-fn relic_values(database: &Database, relics: &HashMap<String, Relic>, relic_count: u32) {
-    let mut sorted_relics: Vec<(String, Refinement, f32)> = relics
+#[derive(Parser, Debug)]
+/// Inspect and rank void relics by their expected platinum or ducat value.
+struct Arguments {
+    #[command(subcommand)]
+    command: Command,
+    #[command(flatten)]
+    data: DataArguments,
+    /// Output format
+    #[arg(short, long, value_enum, global = true, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+    /// What items are valued in
+    #[arg(long, value_enum, global = true, default_value_t = ValueKind::Platinum)]
+    value: ValueKind,
+    /// Platinum per ducat used by the blend valuation
+    #[arg(long, global = true, default_value_t = 0.1)]
+    ducat_rate: f32,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Rank relics by their expected value
+    List {
+        /// Only include relics of this tier: lith, meso, neo or axi. Can be given multiple times
+        #[arg(short, long)]
+        tier: Vec<RelicTier>,
+        /// Only consider this refinement, can be given multiple times. The most valuable of the
+        /// considered refinements is shown for each relic
+        #[arg(long)]
+        refinement: Vec<Refinement>,
+        /// Number of relics cracked in the squad
+        #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=4))]
+        relic_count: u32,
+        /// Only include vaulted relics
+        #[arg(long, conflicts_with = "unvaulted")]
+        vaulted: bool,
+        /// Only include relics that are not vaulted
+        #[arg(long)]
+        unvaulted: bool,
+        /// Only show the first N relics
+        #[arg(short = 'n', long)]
+        top: Option<usize>,
+        /// Sort order of the list
+        #[arg(short, long, value_enum, default_value_t = SortKey::Value)]
+        sort: SortKey,
+        /// Reverse the sort order
+        #[arg(long)]
+        reverse: bool,
+        /// Also show the chance of getting a reward worth at least this much
+        #[arg(long)]
+        at_least: Option<f32>,
+    },
+    /// Recommend which owned relics to refine with the available void traces. Without owned
+    /// relics, shows the gain of refining every intact relic
+    #[command(alias = "tracedump")]
+    Traces {
+        /// Void traces available to spend, required with --owned
+        #[arg(short, long)]
+        balance: Option<u32>,
+        /// Owned relics like "Lith A1=3" or "Lith A1:Exceptional=2", can be given multiple times
+        #[arg(short, long)]
+        owned: Vec<OwnedRelic>,
+        /// Number of relics cracked in the squad
+        #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=4))]
+        relic_count: u32,
+    },
+    /// Show the drop table and expected values of a single relic, e.g. "Lith A1"
+    Relic {
+        /// Relic tier and name
+        #[arg(required = true, num_args = 1..=2)]
+        relic: Vec<String>,
+        /// Also show the expected value for a squad cracking this relic at mixed refinements,
+        /// one per squad member, e.g. intact,intact,radiant
+        #[arg(long, value_delimiter = ',')]
+        squad: Vec<Refinement>,
+        /// Also print the expected values as exact fractions, computed without rounding errors
+        #[arg(long)]
+        exact: bool,
+    },
+    /// List every relic that drops an item, e.g. "Octavia Prime Systems"
+    Item {
+        /// Item name, matched fuzzily against the database
+        #[arg(required = true)]
+        item: Vec<String>,
+        /// Number of relics cracked in the squad
+        #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=4))]
+        relic_count: u32,
+    },
+    /// Keep track of owned relics and decide which one to crack next
+    Inventory {
+        /// Inventory file, defaults to $XDG_DATA_HOME/wfinfo-ng/inventory.json
+        #[arg(long, global = true)]
+        inventory: Option<PathBuf>,
+        #[command(subcommand)]
+        action: InventoryAction,
+    },
+    /// Show the relics whose expected value rose or fell the most between two price snapshots.
+    /// A snapshot of the prices is kept every day the tools are used
+    History {
+        /// Compare from the last snapshot on or before this date, defaults to the oldest one
+        #[arg(long)]
+        from: Option<SnapshotDate>,
+        /// Compare to the last snapshot on or before this date, defaults to the newest one
+        #[arg(long)]
+        to: Option<SnapshotDate>,
+        /// Only include relics of this tier: lith, meso, neo or axi. Can be given multiple times
+        #[arg(short, long)]
+        tier: Vec<RelicTier>,
+        /// Refinement the relics are cracked at
+        #[arg(long, default_value = "intact")]
+        refinement: Refinement,
+        /// Number of relics cracked in the squad
+        #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=4))]
+        relic_count: u32,
+        /// Number of risers and fallers shown per tier
+        #[arg(short = 'n', long, default_value_t = 5)]
+        top: usize,
+        /// List the stored snapshots instead
+        #[arg(long)]
+        list: bool,
+        /// Store a dated copy of the current prices first. Downloaded prices are stored
+        /// automatically, this is for prices from --data-dir
+        #[arg(long)]
+        record: bool,
+    },
+    /// Compare the price of every set to the price of its parts sold individually
+    Sets {
+        /// Only show sets whose name contains this, e.g. "Titania"
+        filter: Option<String>,
+        /// Only include vaulted sets
+        #[arg(long)]
+        vaulted: bool,
+    },
+    /// Look up the items named in a region of a screenshot, like the overlay's snap-it hotkey
+    Snap {
+        /// Screenshot to read
+        screenshot: PathBuf,
+        /// Region to read as x,y,width,height in pixels, defaults to the whole screenshot
+        #[arg(long)]
+        region: Option<Region>,
+    },
+    /// Value the items in trade chat messages, e.g. "WTS [Octavia Prime Systems] 45p". Without a
+    /// message every line read from stdin is checked
+    PriceCheck { message: Vec<String> },
+}
+
+#[derive(Subcommand, Debug)]
+enum InventoryAction {
+    /// List the owned relics and parts
+    Show,
+    /// Add relics like "Lith A1=3" or "Lith A1:Radiant=2"
+    Add {
+        #[arg(required = true)]
+        relics: Vec<OwnedRelic>,
+    },
+    /// Remove relics like "Lith A1=3", e.g. after cracking or selling them
+    Remove {
+        #[arg(required = true)]
+        relics: Vec<OwnedRelic>,
+    },
+    /// Replace the owned count of relics, a count of 0 removes them
+    Set {
+        #[arg(required = true)]
+        relics: Vec<OwnedRelic>,
+    },
+    /// Rank the owned relics by the expected value of cracking one of them
+    Plan {
+        /// Number of relics cracked in the squad, 1 for solo
+        #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=4))]
+        relic_count: u32,
+        /// Only show the first N relics
+        #[arg(short = 'n', long)]
+        top: Option<usize>,
+    },
+    /// Add prime parts like "Titania Prime Systems=2", names are matched fuzzily
+    AddParts {
+        #[arg(required = true)]
+        parts: Vec<OwnedPart>,
+    },
+    /// Remove prime parts like "Titania Prime Systems=2"
+    RemoveParts {
+        #[arg(required = true)]
+        parts: Vec<OwnedPart>,
+    },
+    /// Replace the owned count of prime parts, a count of 0 removes them
+    SetParts {
+        #[arg(required = true)]
+        parts: Vec<OwnedPart>,
+    },
+    /// Read the prime parts from screenshots of the inventory or foundry grid and show how the
+    /// owned counts would change. Experimental, the grid layout has not been checked against
+    /// captures of the game yet
+    #[command(hide = true)]
+    Scan {
+        /// Screenshots of the prime part grid, together showing every part to update
+        #[arg(required = true)]
+        screenshots: Vec<PathBuf>,
+        /// Save the changed counts to the inventory
+        #[arg(long)]
+        apply: bool,
+        /// Run the scan even though it is not validated yet
+        #[arg(long)]
+        experimental: bool,
+    },
+    /// Recommend which complete sets to sell whole and which parts to sell individually
+    Sell,
+    /// Recommend which owned parts to trade to Baro Ki'Teer for ducats
+    Ducats {
+        /// Only trade parts selling for at most this much platinum per ducat, defaults to
+        /// --ducat-rate
+        #[arg(long)]
+        rate: Option<f32>,
+        /// Never trade this part, or keep enough parts to complete one of this set. Can be given
+        /// multiple times
+        #[arg(short, long)]
+        keep: Vec<String>,
+        /// Also trade the parts needed to complete sets of which some parts are owned, which are
+        /// kept by default
+        #[arg(long)]
+        trade_set_parts: bool,
+        /// Only trade enough parts to gain this many ducats, forgoing as little platinum as possible
+        #[arg(short, long)]
+        target: Option<u32>,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum ValueKind {
+    Platinum,
+    Ducats,
+    /// The higher of platinum and ducats times --ducat-rate, per item
+    Blend,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum SortKey {
+    /// Highest expected value first
+    Value,
+    /// Highest variance of the reward value first
+    Variance,
+    /// Highest median reward value first
+    Median,
+    /// Highest chance of a reward worth at least --at-least first
+    AtLeast,
+    /// Highest chance of any rare drop in the squad first
+    RareChance,
+    /// By relic name, then tier
+    Name,
+    /// By tier, then relic name
+    Tier,
+}
+
+const SQUAD_SIZES: [u32; 4] = [1, 2, 3, 4];
+
+fn best_refinement_value(
+    database: &Database,
+    relic: &Relic,
+    refinements: &[Refinement],
+    relic_count: u32,
+    valuation: Valuation,
+) -> (Refinement, f32) {
+    refinements
         .iter()
-        .map(|(name, item)| {
-            let (refinement, value) = [
-                Refinement::Intact,
-                Refinement::Exceptional,
-                Refinement::Flawless,
-                Refinement::Radiant,
-            ]
-            .into_iter()
-            .map(|refinement| {
-                (
-                    refinement,
-                    database.shared_relic_value(item, refinement, relic_count),
-                )
-            })
-            .max_by(|a, b| a.1.total_cmp(&b.1))
-            .unwrap();
-            (name.to_owned(), refinement, value)
+        .map(|&refinement| {
+            (
+                refinement,
+                database.shared_relic_value_in(relic, refinement, relic_count, valuation),
+            )
         })
-        .collect();
-    sorted_relics.sort_by(|a, b| b.2.total_cmp(&a.2));
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+}
+
+#[derive(Serialize)]
+struct RelicRow {
+    #[serde(flatten)]
+    relic: RelicId,
+    vaulted: bool,
+    refinement: Refinement,
+    value: f32,
+    variance: f32,
+    median: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    at_least_chance: Option<f32>,
+    rare_chance: f32,
+}
 
-    let list_length = 800;
-    sorted_relics
+struct ListOptions {
+    tiers: Vec<RelicTier>,
+    refinements: Vec<Refinement>,
+    relic_count: u32,
+    valuation: Valuation,
+    vaulted: Option<bool>,
+    top: Option<usize>,
+    sort: SortKey,
+    reverse: bool,
+    at_least: Option<f32>,
+}
+
+fn relic_values(database: &Database, options: &ListOptions) -> Vec<RelicRow> {
+    let mut rows: Vec<RelicRow> = options
+        .tiers
         .iter()
-        .take(list_length / 2)
-        .for_each(|(name, refinement, value)| println!("{}:\t{:?}\t{}", name, refinement, value));
-    if sorted_relics.len() > list_length / 2 {
-        println!("...");
-        sorted_relics
+        .flat_map(|&tier| {
+            database
+                .relics
+                .tier(tier)
+                .iter()
+                .map(move |(name, relic)| (RelicId::new(tier, name), relic))
+        })
+        .filter(|(_, relic)| options.vaulted.is_none() || options.vaulted == Some(relic.vaulted))
+        .map(|(id, relic)| {
+            let (refinement, value) = best_refinement_value(
+                database,
+                relic,
+                &options.refinements,
+                options.relic_count,
+                options.valuation,
+            );
+            let distribution = database.shared_relic_distribution(
+                relic,
+                refinement,
+                options.relic_count,
+                options.valuation,
+            );
+            RelicRow {
+                relic: id,
+                vaulted: relic.vaulted,
+                refinement,
+                value,
+                variance: distribution.variance(),
+                median: distribution.median(),
+                at_least_chance: options
+                    .at_least
+                    .map(|at_least| distribution.probability_at_least(at_least)),
+                rare_chance: database.any_rare_chance(relic, refinement, options.relic_count),
+            }
+        })
+        .collect();
+
+    match options.sort {
+        SortKey::Value => rows.sort_by(|a, b| b.value.total_cmp(&a.value)),
+        SortKey::Variance => rows.sort_by(|a, b| b.variance.total_cmp(&a.variance)),
+        SortKey::Median => rows.sort_by(|a, b| b.median.total_cmp(&a.median)),
+        SortKey::AtLeast => rows.sort_by(|a, b| {
+            b.at_least_chance
+                .unwrap_or_default()
+                .total_cmp(&a.at_least_chance.unwrap_or_default())
+        }),
+        SortKey::RareChance => rows.sort_by(|a, b| b.rare_chance.total_cmp(&a.rare_chance)),
+        SortKey::Name => rows.sort_by(|a, b| {
+            a.relic
+                .name
+                .cmp(&b.relic.name)
+                .then(a.relic.tier.cmp(&b.relic.tier))
+        }),
+        SortKey::Tier => rows.sort_by(|a, b| a.relic.cmp(&b.relic)),
+    }
+    if options.reverse {
+        rows.reverse();
+    }
+    if let Some(top) = options.top {
+        rows.truncate(top);
+    }
+    rows
+}
+
+fn print_relic_rows(rows: &[RelicRow], format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            print!(
+                "{:<12}{:<13}{:>10}{:>10}{:>10}",
+                "Relic", "Refinement", "Value", "Std dev", "Median"
+            );
+            if rows.iter().any(|row| row.at_least_chance.is_some()) {
+                print!("{:>10}", "At least");
+            }
+            println!("{:>10}  Vaulted", "Rare");
+            for row in rows {
+                print!(
+                    "{:<12}{:<13}{:>10.2}{:>10.2}{:>10.2}",
+                    row.relic.to_string(),
+                    format!("{:?}", row.refinement),
+                    row.value,
+                    row.variance.sqrt(),
+                    row.median
+                );
+                if let Some(at_least_chance) = row.at_least_chance {
+                    print!("{:>9.2}%", at_least_chance * 100.0);
+                }
+                println!(
+                    "{:>9.2}%  {}",
+                    row.rare_chance * 100.0,
+                    if row.vaulted { "Yes" } else { "No" }
+                );
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(rows).expect("Failed to serialize relic list")
+            );
+        }
+        OutputFormat::Csv => {
+            println!(
+                "tier,name,vaulted,refinement,value,variance,median,at_least_chance,rare_chance"
+            );
+            for row in rows {
+                println!(
+                    "{},{},{},{:?},{},{},{},{},{}",
+                    row.relic.tier,
+                    row.relic.name,
+                    row.vaulted,
+                    row.refinement,
+                    row.value,
+                    row.variance,
+                    row.median,
+                    row.at_least_chance
+                        .map(|chance| chance.to_string())
+                        .unwrap_or_default(),
+                    row.rare_chance
+                );
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MarginalGainRow {
+    #[serde(flatten)]
+    relic: RelicId,
+    options: Vec<RefinementOption>,
+}
+
+impl MarginalGainRow {
+    fn best_gain_per_trace(&self) -> Option<&RefinementOption> {
+        self.options
             .iter()
-            .rev()
-            .take((list_length / 2).min(sorted_relics.len() - (list_length / 2)))
-            .rev()
-            .for_each(|(name, refinement, value)| {
-                println!("{}:\t{:?}\t{}", name, refinement, value)
+            .max_by(|a, b| a.gain_per_trace().total_cmp(&b.gain_per_trace()))
+    }
+}
+
+#[derive(Serialize)]
+struct TraceReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plan: Option<TracePlan>,
+    marginal_gains: Vec<MarginalGainRow>,
+}
+
+fn trace_report(
+    database: &Database,
+    balance: u32,
+    owned: &[OwnedRelic],
+    relic_count: u32,
+    valuation: Valuation,
+) -> anyhow::Result<TraceReport> {
+    let mut marginal_gains = Vec::new();
+    if owned.is_empty() {
+        for (id, relic) in database.relics.iter() {
+            marginal_gains.push(MarginalGainRow {
+                relic: id,
+                options: refinement_options(
+                    database,
+                    relic,
+                    Refinement::Intact,
+                    relic_count,
+                    valuation,
+                ),
+            });
+        }
+    } else {
+        for stack in owned {
+            let relic = database
+                .relics
+                .get(&stack.relic)
+                .ok_or_else(|| anyhow!("Unknown relic: {}", stack.relic))?;
+            marginal_gains.push(MarginalGainRow {
+                relic: stack.relic.clone(),
+                options: refinement_options(
+                    database,
+                    relic,
+                    stack.refinement,
+                    relic_count,
+                    valuation,
+                ),
             });
+        }
     }
+    marginal_gains.sort_by(|a, b| {
+        let gain = |row: &MarginalGainRow| {
+            row.best_gain_per_trace()
+                .map_or(0.0, RefinementOption::gain_per_trace)
+        };
+        gain(b).total_cmp(&gain(a))
+    });
+
+    let plan = if owned.is_empty() {
+        None
+    } else {
+        Some(plan_trace_spending(
+            database,
+            owned,
+            balance,
+            relic_count,
+            valuation,
+        )?)
+    };
+
+    Ok(TraceReport {
+        plan,
+        marginal_gains,
+    })
 }
 
-fn best_trace_dump(database: &Database) {
-    let mut relics = Vec::new();
-    for (prefix, relic_group) in [
-        ("Lith", &database.relics.lith),
-        ("Meso", &database.relics.meso),
-        ("Neo", &database.relics.neo),
-        ("Axi", &database.relics.axi),
-    ] {
-        for (name, relic) in relic_group.iter() {
-            let intact = database.shared_relic_value(relic, Refinement::Intact, 4);
-            let radiant = database.shared_relic_value(relic, Refinement::Radiant, 4);
-            relics.push((format!("{prefix} {name}"), radiant - intact));
+fn print_trace_report(report: &TraceReport, balance: u32, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            if let Some(plan) = report.plan.as_ref() {
+                println!(
+                    "Spend {} of {} void traces for an expected gain of {:.2}",
+                    plan.traces_spent, balance, plan.total_gain
+                );
+                println!();
+                println!(
+                    "{:<12}{:<13}{:<13}{:>6}{:>8}{:>10}{:>22}",
+                    "Relic", "From", "To", "Count", "Traces", "Gain", "Gain per 100 traces"
+                );
+                for upgrade in plan.upgrades.iter() {
+                    println!(
+                        "{:<12}{:<13}{:<13}{:>6}{:>8}{:>10.2}{:>22.2}",
+                        upgrade.relic.to_string(),
+                        format!("{:?}", upgrade.from),
+                        format!("{:?}", upgrade.to),
+                        upgrade.count,
+                        upgrade.traces,
+                        upgrade.gain,
+                        upgrade.gain / upgrade.traces as f32 * 100.0
+                    );
+                }
+                println!();
+            }
+
+            println!("Gain per relic for each refinement (traces spent in parentheses)");
+            print!("{:<12}{:<13}", "Relic", "From");
+            for refinement in &Refinement::ALL[1..] {
+                print!("{:>18}", format!("{refinement:?}"));
+            }
+            println!("{:>22}", "Best per 100 traces");
+            for row in report.marginal_gains.iter() {
+                let from = row
+                    .options
+                    .first()
+                    .map_or(Refinement::Radiant, |option| option.from);
+                print!("{:<12}{:<13}", row.relic.to_string(), format!("{from:?}"));
+                for refinement in &Refinement::ALL[1..] {
+                    match row.options.iter().find(|option| option.to == *refinement) {
+                        Some(option) => {
+                            print!("{:>18}", format!("{:+.2} ({})", option.gain, option.traces))
+                        }
+                        None => print!("{:>18}", "-"),
+                    }
+                }
+                match row.best_gain_per_trace() {
+                    Some(option) => println!(
+                        "{:>22}",
+                        format!("{:.2} ({:?})", option.gain_per_trace() * 100.0, option.to)
+                    ),
+                    None => println!("{:>22}", "-"),
+                }
+            }
         }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(report).expect("Failed to serialize trace report")
+            );
+        }
+        OutputFormat::Csv => match report.plan.as_ref() {
+            Some(plan) => {
+                println!("tier,name,from,to,count,traces,gain");
+                for upgrade in plan.upgrades.iter() {
+                    println!(
+                        "{},{},{:?},{:?},{},{},{}",
+                        upgrade.relic.tier,
+                        upgrade.relic.name,
+                        upgrade.from,
+                        upgrade.to,
+                        upgrade.count,
+                        upgrade.traces,
+                        upgrade.gain
+                    );
+                }
+            }
+            None => {
+                println!("tier,name,from,to,traces,gain");
+                for row in report.marginal_gains.iter() {
+                    for option in row.options.iter() {
+                        println!(
+                            "{},{},{:?},{:?},{},{}",
+                            row.relic.tier,
+                            row.relic.name,
+                            option.from,
+                            option.to,
+                            option.traces,
+                            option.gain
+                        );
+                    }
+                }
+            }
+        },
+    }
+}
+
+#[derive(Serialize)]
+struct RelicReport {
+    #[serde(flatten)]
+    relic: RelicId,
+    vaulted: bool,
+    drops: Vec<DropReport>,
+    expected_values: Vec<ExpectedValueReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mixed_squad: Option<MixedSquadReport>,
+}
+
+#[derive(Serialize)]
+struct MixedSquadReport {
+    refinements: Vec<Refinement>,
+    value: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exact_value: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DropReport {
+    item: String,
+    rarity: Rarity,
+    chances: IndexMap<Refinement, f32>,
+    platinum: f32,
+    ducats: usize,
+}
+
+#[derive(Serialize)]
+struct ExpectedValueReport {
+    squad_size: u32,
+    values: IndexMap<Refinement, f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exact_values: Option<IndexMap<Refinement, String>>,
+}
+
+#[derive(Serialize)]
+struct ItemSourcesReport {
+    item: String,
+    vaulted: bool,
+    relic_count: u32,
+    relics: Vec<ItemSourceReport>,
+}
+
+#[derive(Serialize)]
+struct ItemSourceReport {
+    #[serde(flatten)]
+    relic: RelicId,
+    rarity: Rarity,
+    vaulted: bool,
+    chances: IndexMap<Refinement, f32>,
+    best_refinement: Refinement,
+    expected_value: f32,
+}
+
+fn refinement_chances(database: &Database, drop: &RelicDrop) -> IndexMap<Refinement, f32> {
+    Refinement::ALL
+        .into_iter()
+        .map(|refinement| {
+            (
+                refinement,
+                database.drop_chances.drop_chance(drop, refinement),
+            )
+        })
+        .collect()
+}
+
+fn relic_report(
+    database: &Database,
+    id: &RelicId,
+    relic: &Relic,
+    squad: &[Refinement],
+    valuation: Valuation,
+    exact: bool,
+) -> RelicReport {
+    let drops = database
+        .relic_items(relic)
+        .into_iter()
+        .map(|(item, drop)| DropReport {
+            item: item.name.clone(),
+            rarity: drop.rarity,
+            chances: refinement_chances(database, drop),
+            platinum: item.platinum,
+            ducats: item.ducats,
+        })
+        .collect();
+    let expected_values = SQUAD_SIZES
+        .into_iter()
+        .map(|squad_size| ExpectedValueReport {
+            squad_size,
+            values: Refinement::ALL
+                .into_iter()
+                .map(|refinement| {
+                    (
+                        refinement,
+                        database.shared_relic_value_in(relic, refinement, squad_size, valuation),
+                    )
+                })
+                .collect(),
+            exact_values: exact.then(|| {
+                Refinement::ALL
+                    .into_iter()
+                    .map(|refinement| {
+                        let value = database
+                            .shared_relic_value_exact(relic, refinement, squad_size, valuation);
+                        (refinement, value.to_string())
+                    })
+                    .collect()
+            }),
+        })
+        .collect();
+    let mixed_squad = (!squad.is_empty()).then(|| MixedSquadReport {
+        refinements: squad.to_vec(),
+        value: database.mixed_relic_value_in(relic, squad, valuation),
+        exact_value: exact.then(|| {
+            database
+                .mixed_relic_value_exact(relic, squad, valuation)
+                .to_string()
+        }),
+    });
+
+    RelicReport {
+        relic: id.clone(),
+        vaulted: relic.vaulted,
+        drops,
+        expected_values,
+        mixed_squad,
     }
+}
 
-    let mut sorted_relics = relics;
-    sorted_relics.sort_by(|a, b| a.1.total_cmp(&b.1));
+fn print_relic_report(report: &RelicReport, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            println!(
+                "{}{}",
+                report.relic,
+                if report.vaulted { " (vaulted)" } else { "" }
+            );
+            println!();
+            print!("{:<40}{:<10}", "Item", "Rarity");
+            for refinement in Refinement::ALL {
+                print!("{:>13}", format!("{refinement:?}"));
+            }
+            println!("{:>10}{:>8}", "Platinum", "Ducats");
+            for drop in report.drops.iter() {
+                print!("{:<40}{:<10}", drop.item, format!("{:?}", drop.rarity));
+                for (_, chance) in drop.chances.iter() {
+                    print!("{:>12.2}%", chance * 100.0);
+                }
+                println!("{:>10.1}{:>8}", drop.platinum, drop.ducats);
+            }
+            println!();
+            print!("{:<50}", "Expected value");
+            for refinement in Refinement::ALL {
+                print!("{:>13}", format!("{refinement:?}"));
+            }
+            println!();
+            for expected_value in report.expected_values.iter() {
+                print!(
+                    "{:<50}",
+                    match expected_value.squad_size {
+                        1 => "Solo".to_string(),
+                        n => format!("{n} player radshare"),
+                    }
+                );
+                for (_, value) in expected_value.values.iter() {
+                    print!("{:>13.2}", value);
+                }
+                println!();
+            }
+            if let Some(mixed_squad) = report.mixed_squad.as_ref() {
+                println!();
+                println!(
+                    "{:<50}{:>13.2}",
+                    format!("Mixed squad {:?}", mixed_squad.refinements),
+                    mixed_squad.value
+                );
+            }
+            if report
+                .expected_values
+                .iter()
+                .any(|expected_value| expected_value.exact_values.is_some())
+            {
+                println!();
+                println!("Exact expected values");
+                for expected_value in report.expected_values.iter() {
+                    for (refinement, value) in expected_value.exact_values.iter().flatten() {
+                        println!(
+                            "{:<26}{}",
+                            format!("{} relics {refinement:?}", expected_value.squad_size),
+                            value
+                        );
+                    }
+                }
+                if let Some(exact_value) = report
+                    .mixed_squad
+                    .as_ref()
+                    .and_then(|mixed_squad| mixed_squad.exact_value.as_ref())
+                {
+                    println!("{:<26}{}", "Mixed squad", exact_value);
+                }
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(report).expect("Failed to serialize relic report")
+            );
+        }
+        OutputFormat::Csv => {
+            let refinements: Vec<_> = Refinement::ALL
+                .iter()
+                .map(|refinement| format!("{refinement:?}"))
+                .collect();
+            println!(
+                "tier,name,vaulted,item,rarity,{},platinum,ducats",
+                refinements.join(",")
+            );
+            for drop in report.drops.iter() {
+                let chances: Vec<_> = drop
+                    .chances
+                    .iter()
+                    .map(|(_, chance)| chance.to_string())
+                    .collect();
+                println!(
+                    "{},{},{},{},{:?},{},{},{}",
+                    report.relic.tier,
+                    report.relic.name,
+                    report.vaulted,
+                    drop.item,
+                    drop.rarity,
+                    chances.join(","),
+                    drop.platinum,
+                    drop.ducats
+                );
+            }
+            println!();
+            println!("tier,name,squad_size,{}", refinements.join(","));
+            for expected_value in report.expected_values.iter() {
+                let values: Vec<_> = expected_value
+                    .values
+                    .iter()
+                    .map(|(_, value)| value.to_string())
+                    .collect();
+                println!(
+                    "{},{},{},{}",
+                    report.relic.tier,
+                    report.relic.name,
+                    expected_value.squad_size,
+                    values.join(",")
+                );
+            }
+            if let Some(mixed_squad) = report.mixed_squad.as_ref() {
+                let refinements: Vec<_> = mixed_squad
+                    .refinements
+                    .iter()
+                    .map(|refinement| format!("{refinement:?}"))
+                    .collect();
+                println!();
+                println!("tier,name,squad,value");
+                println!(
+                    "{},{},{},{}",
+                    report.relic.tier,
+                    report.relic.name,
+                    refinements.join(";"),
+                    mixed_squad.value
+                );
+            }
+            if report
+                .expected_values
+                .iter()
+                .any(|expected_value| expected_value.exact_values.is_some())
+            {
+                println!();
+                println!("tier,name,squad_size,refinement,exact_value");
+                for expected_value in report.expected_values.iter() {
+                    for (refinement, value) in expected_value.exact_values.iter().flatten() {
+                        println!(
+                            "{},{},{},{:?},{}",
+                            report.relic.tier,
+                            report.relic.name,
+                            expected_value.squad_size,
+                            refinement,
+                            value
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
 
-    println!("...");
-    sorted_relics
+fn item_sources_report(
+    database: &Database,
+    needle: &str,
+    relic_count: u32,
+    valuation: Valuation,
+) -> anyhow::Result<ItemSourcesReport> {
+    let (item, sources) = database
+        .find_relics_dropping(needle)
+        .ok_or_else(|| anyhow!("Unknown item: {needle}"))?;
+    let relics = sources
         .iter()
-        .for_each(|(name, value)| println!("{}:  \t{}", name, value));
-}
-
-fn main() {
-    let database = Database::load_from_file(None, None);
-    let mut args = std::env::args().skip(1);
-    let relics = match args
-        .next()
-        .expect("No relic type provided")
-        .to_lowercase()
-        .as_str()
-    {
-        "lith" => &database.relics.lith,
-        "meso" => &database.relics.meso,
-        "neo" => &database.relics.neo,
-        "axi" => &database.relics.axi,
-        "tracedump" => {
-            best_trace_dump(&database);
-            return;
-        }
-        s => panic!("Invalid relic type: {s}"),
+        .map(|source| {
+            let relic = database
+                .relics
+                .get(&source.relic)
+                .expect("Relic source refers to an unknown relic");
+            let drop = relic
+                .rewards
+                .iter()
+                .find(|drop| drop.item == item.name && drop.rarity == source.rarity)
+                .expect("Relic source refers to an item the relic doesn't drop");
+            let (best_refinement, expected_value) =
+                best_refinement_value(database, relic, &Refinement::ALL, relic_count, valuation);
+            ItemSourceReport {
+                relic: source.relic.clone(),
+                rarity: source.rarity,
+                vaulted: source.vaulted,
+                chances: refinement_chances(database, drop),
+                best_refinement,
+                expected_value,
+            }
+        })
+        .collect();
+
+    Ok(ItemSourcesReport {
+        item: item.name.clone(),
+        vaulted: item.vaulted,
+        relic_count,
+        relics,
+    })
+}
+
+fn print_item_sources_report(report: &ItemSourcesReport, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            println!(
+                "{}{}",
+                report.item,
+                if report.vaulted { " (vaulted)" } else { "" }
+            );
+            println!();
+            print!("{:<12}{:<10}{:<9}", "Relic", "Rarity", "Vaulted");
+            for refinement in Refinement::ALL {
+                print!("{:>13}", format!("{refinement:?}"));
+            }
+            println!(
+                "{:>22}",
+                format!("Expected ({} relics)", report.relic_count)
+            );
+            for relic in report.relics.iter() {
+                print!(
+                    "{:<12}{:<10}{:<9}",
+                    relic.relic.to_string(),
+                    format!("{:?}", relic.rarity),
+                    if relic.vaulted { "Yes" } else { "No" }
+                );
+                for (_, chance) in relic.chances.iter() {
+                    print!("{:>12.2}%", chance * 100.0);
+                }
+                println!(
+                    "{:>22}",
+                    format!("{:.2} ({:?})", relic.expected_value, relic.best_refinement)
+                );
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(report).expect("Failed to serialize item report")
+            );
+        }
+        OutputFormat::Csv => {
+            let refinements: Vec<_> = Refinement::ALL
+                .iter()
+                .map(|refinement| format!("{refinement:?}"))
+                .collect();
+            println!(
+                "item,tier,name,rarity,vaulted,{},best_refinement,expected_value",
+                refinements.join(",")
+            );
+            for relic in report.relics.iter() {
+                let chances: Vec<_> = relic
+                    .chances
+                    .iter()
+                    .map(|(_, chance)| chance.to_string())
+                    .collect();
+                println!(
+                    "{},{},{},{:?},{},{},{:?},{}",
+                    report.item,
+                    relic.relic.tier,
+                    relic.relic.name,
+                    relic.rarity,
+                    relic.vaulted,
+                    chances.join(","),
+                    relic.best_refinement,
+                    relic.expected_value
+                );
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ScanReport {
+    changes: Vec<PartChange>,
+    unknown: Vec<String>,
+    applied: bool,
+}
+
+fn print_scan_report(report: &ScanReport, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            if report.changes.is_empty() {
+                println!("The scanned parts match the inventory");
+            } else {
+                println!("{:<40}{:>8}{:>8}{:>8}", "Part", "Before", "After", "Change");
+                for change in report.changes.iter() {
+                    println!(
+                        "{:<40}{:>8}{:>8}{:>+8}",
+                        change.name,
+                        change.before,
+                        change.after,
+                        change.after as i64 - change.before as i64
+                    );
+                }
+            }
+            if !report.unknown.is_empty() {
+                println!();
+                println!("Unrecognised tiles:");
+                for name in report.unknown.iter() {
+                    println!("  {name}");
+                }
+            }
+            if !report.changes.is_empty() {
+                println!();
+                if report.applied {
+                    println!("Saved {} changes to the inventory", report.changes.len());
+                } else {
+                    println!("Run again with --apply to save these changes");
+                }
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(report).expect("Failed to serialize scan report")
+            );
+        }
+        OutputFormat::Csv => {
+            println!("part,before,after");
+            for change in report.changes.iter() {
+                println!("{},{},{}", change.name, change.before, change.after);
+            }
+        }
+    }
+}
+
+fn print_inventory(inventory: &Inventory, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            println!("{:<12}{:<13}{:>6}", "Relic", "Refinement", "Count");
+            for stack in inventory.relics.iter() {
+                println!(
+                    "{:<12}{:<13}{:>6}",
+                    stack.relic.to_string(),
+                    format!("{:?}", stack.refinement),
+                    stack.count
+                );
+            }
+            if !inventory.parts.is_empty() {
+                println!();
+                println!("{:<40}{:>6}", "Part", "Count");
+                for parts in inventory.parts.iter() {
+                    println!("{:<40}{:>6}", parts.name, parts.count);
+                }
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(inventory).expect("Failed to serialize inventory")
+            );
+        }
+        OutputFormat::Csv => {
+            println!("tier,name,refinement,count");
+            for stack in inventory.relics.iter() {
+                println!(
+                    "{},{},{:?},{}",
+                    stack.relic.tier, stack.relic.name, stack.refinement, stack.count
+                );
+            }
+            if !inventory.parts.is_empty() {
+                println!();
+                println!("part,count");
+                for parts in inventory.parts.iter() {
+                    println!("{},{}", parts.name, parts.count);
+                }
+            }
+        }
+    }
+}
+
+fn print_sale_plan(plan: &SalePlan, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            println!(
+                "Selling everything is worth {:.0} platinum",
+                plan.total_platinum
+            );
+            if !plan.sets.is_empty() {
+                println!();
+                println!(
+                    "{:<40}{:>6}{:>10}{:>10}",
+                    "Sell as set", "Count", "Set", "Parts"
+                );
+                for sale in plan.sets.iter() {
+                    println!(
+                        "{:<40}{:>6}{:>10.1}{:>10.1}",
+                        sale.set, sale.count, sale.set_platinum, sale.parts_platinum
+                    );
+                }
+            }
+            if !plan.parts.is_empty() {
+                println!();
+                println!("{:<40}{:>6}{:>10}", "Sell as part", "Count", "Platinum");
+                for sale in plan.parts.iter() {
+                    println!("{:<40}{:>6}{:>10.1}", sale.name, sale.count, sale.platinum);
+                }
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(plan).expect("Failed to serialize sale plan")
+            );
+        }
+        OutputFormat::Csv => {
+            println!("kind,name,count,platinum");
+            for sale in plan.sets.iter() {
+                println!("set,{},{},{}", sale.set, sale.count, sale.set_platinum);
+            }
+            for sale in plan.parts.iter() {
+                println!("part,{},{},{}", sale.name, sale.count, sale.platinum);
+            }
+        }
+    }
+}
+
+fn print_trade_in_plan(plan: &TradeInPlan, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            println!(
+                "Trade for {} ducats, forgoing {:.0} platinum",
+                plan.ducats_gained, plan.platinum_forgone
+            );
+            println!();
+            println!(
+                "{:<40}{:>6}{:>8}{:>10}{:>14}",
+                "Part", "Count", "Ducats", "Platinum", "Plat/ducat"
+            );
+            for trade in plan.trades.iter() {
+                println!(
+                    "{:<40}{:>6}{:>8}{:>10.1}{:>14.3}",
+                    trade.name,
+                    trade.count,
+                    trade.ducats,
+                    trade.platinum,
+                    trade.platinum_per_ducat()
+                );
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(plan).expect("Failed to serialize trade-in plan")
+            );
+        }
+        OutputFormat::Csv => {
+            println!("name,count,ducats,platinum");
+            for trade in plan.trades.iter() {
+                println!(
+                    "{},{},{},{}",
+                    trade.name, trade.count, trade.ducats, trade.platinum
+                );
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SetRow {
+    name: String,
+    vaulted: bool,
+    platinum: f32,
+    parts_platinum: Option<f32>,
+    parts: Vec<SetPartRow>,
+}
+
+#[derive(Serialize)]
+struct SetPartRow {
+    name: String,
+    count: u32,
+    platinum: Option<f32>,
+}
+
+fn set_rows(database: &Database, filter: Option<&str>, vaulted: bool) -> Vec<SetRow> {
+    let filter = filter.unwrap_or_default().to_lowercase();
+    let mut rows: Vec<SetRow> = database
+        .sets()
+        .iter()
+        .filter(|set| !vaulted || set.vaulted)
+        .filter(|set| set.name.to_lowercase().contains(&filter))
+        .map(|set| SetRow {
+            name: set.name.clone(),
+            vaulted: set.vaulted,
+            platinum: set.platinum,
+            parts_platinum: database.parts_platinum(set),
+            parts: set
+                .parts
+                .iter()
+                .map(|part| SetPartRow {
+                    name: part.name.clone(),
+                    count: part.count,
+                    platinum: database
+                        .find_item_exact(&part.name)
+                        .map(|item| item.platinum),
+                })
+                .collect(),
+        })
+        .collect();
+    // Sets most worth selling whole first
+    rows.sort_by(|a, b| {
+        let premium = |row: &SetRow| row.platinum - row.parts_platinum.unwrap_or(row.platinum);
+        premium(b).total_cmp(&premium(a))
+    });
+    rows
+}
+
+fn print_set_rows(rows: &[SetRow], format: OutputFormat) {
+    let optional = |platinum: Option<f32>| platinum.map_or("-".to_string(), |p| format!("{p:.1}"));
+    match format {
+        OutputFormat::Table => {
+            println!(
+                "{:<40}{:>10}{:>10}{:>10}  Vaulted",
+                "Set", "Set", "Parts", "Premium"
+            );
+            for row in rows {
+                println!(
+                    "{:<40}{:>10.1}{:>10}{:>10}  {}",
+                    row.name,
+                    row.platinum,
+                    optional(row.parts_platinum),
+                    optional(row.parts_platinum.map(|parts| row.platinum - parts)),
+                    if row.vaulted { "Yes" } else { "No" }
+                );
+                for part in row.parts.iter() {
+                    println!(
+                        "  {:<38}{:>10}",
+                        match part.count {
+                            1 => part.name.clone(),
+                            count => format!("{} x{count}", part.name),
+                        },
+                        optional(part.platinum)
+                    );
+                }
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(rows).expect("Failed to serialize set list")
+            );
+        }
+        OutputFormat::Csv => {
+            println!("set,vaulted,platinum,parts_platinum");
+            for row in rows {
+                println!(
+                    "{},{},{},{}",
+                    row.name,
+                    row.vaulted,
+                    row.platinum,
+                    row.parts_platinum
+                        .map(|platinum| platinum.to_string())
+                        .unwrap_or_default()
+                );
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SnapRow {
+    name: String,
+    platinum: f32,
+    ducats: usize,
+    vaulted: bool,
+}
+
+fn print_snap_rows(rows: &[SnapRow], format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            if rows.is_empty() {
+                println!("No items found");
+                return;
+            }
+            println!("{:<40}{:>10}{:>8}  Vaulted", "Item", "Platinum", "Ducats");
+            for row in rows {
+                println!(
+                    "{:<40}{:>10.1}{:>8}  {}",
+                    row.name,
+                    row.platinum,
+                    row.ducats,
+                    if row.vaulted { "Yes" } else { "No" }
+                );
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(rows).expect("Failed to serialize snapped items")
+            );
+        }
+        OutputFormat::Csv => {
+            println!("name,platinum,ducats,vaulted");
+            for row in rows {
+                println!(
+                    "{},{},{},{}",
+                    row.name, row.platinum, row.ducats, row.vaulted
+                );
+            }
+        }
+    }
+}
+
+fn print_price_checks(message: &str, checks: &[PriceCheck], format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            println!("{message}");
+            for check in checks {
+                println!(
+                    "  {:<38}{:>10.1}{:>8}  {}",
+                    check.name,
+                    check.platinum,
+                    check
+                        .ducats
+                        .map_or("-".to_string(), |ducats| ducats.to_string()),
+                    if check.vaulted { "Vaulted" } else { "" }
+                );
+            }
+            if checks.is_empty() {
+                println!("  No items found");
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string(checks).expect("Failed to serialize price checks")
+            );
+        }
+        OutputFormat::Csv => {
+            for check in checks {
+                println!(
+                    "{},{},{},{},{}",
+                    check.mention,
+                    check.name,
+                    check.platinum,
+                    check
+                        .ducats
+                        .map(|ducats| ducats.to_string())
+                        .unwrap_or_default(),
+                    check.vaulted
+                );
+            }
+        }
+    }
+}
+
+fn print_crack_advice(advice: &[CrackAdvice], format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            println!(
+                "{:<12}{:<13}{:>6}{:>10}{:>10}  Vaulted",
+                "Relic", "Refinement", "Count", "Value", "Sell"
+            );
+            for crack in advice {
+                println!(
+                    "{:<12}{:<13}{:>6}{:>10.2}{:>10}  {}{}",
+                    crack.relic.relic.to_string(),
+                    format!("{:?}", crack.relic.refinement),
+                    crack.relic.count,
+                    crack.value,
+                    crack
+                        .sell_price
+                        .map(|price| format!("{price:.2}"))
+                        .unwrap_or_else(|| "-".to_string()),
+                    if crack.vaulted { "Yes" } else { "No" },
+                    if crack.sell_instead {
+                        " (worth more sold)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(advice).expect("Failed to serialize crack plan")
+            );
+        }
+        OutputFormat::Csv => {
+            println!("tier,name,refinement,count,vaulted,value,platinum,sell_price,sell_instead");
+            for crack in advice {
+                println!(
+                    "{},{},{:?},{},{},{},{},{},{}",
+                    crack.relic.relic.tier,
+                    crack.relic.relic.name,
+                    crack.relic.refinement,
+                    crack.relic.count,
+                    crack.vaulted,
+                    crack.value,
+                    crack.platinum,
+                    crack
+                        .sell_price
+                        .map(|price| price.to_string())
+                        .unwrap_or_default(),
+                    crack.sell_instead
+                );
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HistoryReport {
+    from: SnapshotDate,
+    to: SnapshotDate,
+    tiers: Vec<TierChanges>,
+}
+
+#[derive(Serialize)]
+struct TierChanges {
+    tier: RelicTier,
+    risers: Vec<ValueChange>,
+    fallers: Vec<ValueChange>,
+}
+
+struct HistoryOptions {
+    from: Option<SnapshotDate>,
+    to: Option<SnapshotDate>,
+    tiers: Vec<RelicTier>,
+    refinement: Refinement,
+    relic_count: u32,
+    top: usize,
+    valuation: Valuation,
+}
+
+fn history_report(
+    history: &PriceHistory,
+    filtered_items: &Path,
+    options: &HistoryOptions,
+) -> anyhow::Result<HistoryReport> {
+    let snapshots = history.snapshots()?;
+    let (Some(oldest), Some(newest)) = (snapshots.first(), snapshots.last()) else {
+        bail!("No price snapshots stored yet");
+    };
+    let from = match options.from {
+        Some(date) => history.snapshot_at(date)?,
+        None => oldest.clone(),
+    };
+    let to = match options.to {
+        Some(date) => history.snapshot_at(date)?,
+        None => newest.clone(),
     };
-    let relic_count: u32 = args
-        .next()
-        .unwrap_or_else(|| "4".to_string())
-        .parse()
-        .expect("Failed to parse relic count");
-    relic_values(&database, relics, relic_count);
+    let before = Database::load_from_file(Some(&from.path), Some(filtered_items))?;
+    let after = Database::load_from_file(Some(&to.path), Some(filtered_items))?;
+    let changes = relic_value_changes(
+        &before,
+        &after,
+        options.refinement,
+        options.relic_count,
+        options.valuation,
+    );
+
+    let tiers = options
+        .tiers
+        .iter()
+        .map(|&tier| {
+            let in_tier = || {
+                changes
+                    .iter()
+                    .filter(move |change| change.relic.tier == tier)
+            };
+            TierChanges {
+                tier,
+                risers: in_tier()
+                    .filter(|change| change.change() > 0.0)
+                    .take(options.top)
+                    .cloned()
+                    .collect(),
+                fallers: in_tier()
+                    .rev()
+                    .filter(|change| change.change() < 0.0)
+                    .take(options.top)
+                    .cloned()
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(HistoryReport {
+        from: from.date,
+        to: to.date,
+        tiers,
+    })
+}
+
+fn print_history_report(report: &HistoryReport, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            println!(
+                "Expected value changes from {} to {}",
+                report.from, report.to
+            );
+            for tier_changes in report.tiers.iter() {
+                for (title, changes) in [
+                    ("risers", &tier_changes.risers),
+                    ("fallers", &tier_changes.fallers),
+                ] {
+                    println!();
+                    println!("{} {title}", tier_changes.tier);
+                    if changes.is_empty() {
+                        println!("None");
+                        continue;
+                    }
+                    println!(
+                        "{:<12}{:>10}{:>10}{:>10}{:>10}",
+                        "Relic", "Before", "After", "Change", "Percent"
+                    );
+                    for change in changes.iter() {
+                        // The relative change is undefined for relics that had no value before
+                        let percent = if change.before > 0.0 {
+                            format!("{:+.1}%", change.change() / change.before * 100.0)
+                        } else {
+                            "new".to_string()
+                        };
+                        println!(
+                            "{:<12}{:>10.2}{:>10.2}{:>+10.2}{:>10}",
+                            change.relic.to_string(),
+                            change.before,
+                            change.after,
+                            change.change(),
+                            percent
+                        );
+                    }
+                }
+            }
+        }
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(report).expect("Failed to serialize history report")
+            );
+        }
+        OutputFormat::Csv => {
+            println!("from,to,tier,name,before,after,change");
+            for tier_changes in report.tiers.iter() {
+                for change in tier_changes.risers.iter().chain(&tier_changes.fallers) {
+                    println!(
+                        "{},{},{},{},{},{},{}",
+                        report.from,
+                        report.to,
+                        change.relic.tier,
+                        change.relic.name,
+                        change.before,
+                        change.after,
+                        change.change()
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let arguments = Arguments::parse();
+    let (prices, filtered_items) = arguments.data.data_files()?;
+    let database = Database::load_from_file(Some(&prices), Some(&filtered_items))?;
+    let format = arguments.format;
+    let valuation = match arguments.value {
+        ValueKind::Platinum => Valuation::Platinum,
+        ValueKind::Ducats => Valuation::Ducats,
+        ValueKind::Blend => Valuation::Blend {
+            platinum_per_ducat: arguments.ducat_rate,
+        },
+    };
+    match arguments.command {
+        Command::List {
+            tier,
+            refinement,
+            relic_count,
+            vaulted,
+            unvaulted,
+            top,
+            sort,
+            reverse,
+            at_least,
+        } => {
+            if sort == SortKey::AtLeast && at_least.is_none() {
+                bail!("Sorting by at-least requires --at-least");
+            }
+            let options = ListOptions {
+                tiers: if tier.is_empty() {
+                    RelicTier::ALL.to_vec()
+                } else {
+                    tier
+                },
+                refinements: if refinement.is_empty() {
+                    Refinement::ALL.to_vec()
+                } else {
+                    refinement
+                },
+                relic_count,
+                valuation,
+                vaulted: match (vaulted, unvaulted) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+                top,
+                sort,
+                reverse,
+                at_least,
+            };
+            print_relic_rows(&relic_values(&database, &options), format);
+        }
+        Command::Traces {
+            balance,
+            owned,
+            relic_count,
+        } => {
+            let balance = match balance {
+                Some(balance) => balance,
+                None if owned.is_empty() => 0,
+                None => bail!("Planning refinements for owned relics requires --balance"),
+            };
+            let report = trace_report(&database, balance, &owned, relic_count, valuation)?;
+            print_trace_report(&report, balance, format);
+        }
+        Command::Relic {
+            relic,
+            squad,
+            exact,
+        } => {
+            if squad.len() > 4 {
+                bail!(
+                    "A squad has at most 4 members, got {} refinements",
+                    squad.len()
+                );
+            }
+            let relic: RelicId = relic.join(" ").parse()?;
+            let Some(found) = database.relics.get(&relic) else {
+                bail!("Unknown relic: {relic}");
+            };
+            let report = relic_report(&database, &relic, found, &squad, valuation, exact);
+            print_relic_report(&report, format);
+        }
+        Command::Item { item, relic_count } => {
+            let report = item_sources_report(&database, &item.join(" "), relic_count, valuation)?;
+            print_item_sources_report(&report, format);
+        }
+        Command::Inventory { inventory, action } => {
+            let path = inventory.unwrap_or_else(Inventory::default_path);
+            let mut inventory = Inventory::load(&path)?;
+            let known = |stack: &OwnedRelic| match database.relics.get(&stack.relic) {
+                Some(_) => Ok(()),
+                None => Err(anyhow!("Unknown relic: {}", stack.relic)),
+            };
+            let canonical = |parts: &OwnedPart| match database.lookup_item(&parts.name) {
+                Some(item) => Ok(OwnedPart {
+                    name: item.name.clone(),
+                    count: parts.count,
+                }),
+                None => Err(anyhow!("Unknown item: {}", parts.name)),
+            };
+            match action {
+                InventoryAction::Show => print_inventory(&inventory, format),
+                InventoryAction::Add { relics } => {
+                    for stack in relics.iter() {
+                        known(stack)?;
+                        inventory.add_relics(stack);
+                    }
+                    inventory.save(&path)?;
+                    print_inventory(&inventory, format);
+                }
+                InventoryAction::Remove { relics } => {
+                    for stack in relics.iter() {
+                        inventory.remove_relics(stack)?;
+                    }
+                    inventory.save(&path)?;
+                    print_inventory(&inventory, format);
+                }
+                InventoryAction::Set { relics } => {
+                    for stack in relics.iter() {
+                        known(stack)?;
+                        inventory.set_relics(stack);
+                    }
+                    inventory.save(&path)?;
+                    print_inventory(&inventory, format);
+                }
+                InventoryAction::Plan { relic_count, top } => {
+                    let mut advice =
+                        plan_cracks(&database, &inventory.relics, relic_count, valuation)?;
+                    if let Some(top) = top {
+                        advice.truncate(top);
+                    }
+                    print_crack_advice(&advice, format);
+                }
+                InventoryAction::AddParts { parts } => {
+                    for parts in parts.iter() {
+                        inventory.add_parts(&canonical(parts)?);
+                    }
+                    inventory.save(&path)?;
+                    print_inventory(&inventory, format);
+                }
+                InventoryAction::RemoveParts { parts } => {
+                    for parts in parts.iter() {
+                        inventory.remove_parts(&canonical(parts)?)?;
+                    }
+                    inventory.save(&path)?;
+                    print_inventory(&inventory, format);
+                }
+                InventoryAction::SetParts { parts } => {
+                    for parts in parts.iter() {
+                        inventory.set_parts(&canonical(parts)?);
+                    }
+                    inventory.save(&path)?;
+                    print_inventory(&inventory, format);
+                }
+                InventoryAction::Scan {
+                    screenshots,
+                    apply,
+                    experimental,
+                } => {
+                    if !experimental {
+                        bail!(
+                            "Inventory scanning is not validated against game screenshots yet, \
+                             pass --experimental to try it"
+                        );
+                    }
+                    let images = screenshots
+                        .iter()
+                        .map(|screenshot| {
+                            image::open(screenshot).map_err(|err| {
+                                anyhow!("Failed to open {}: {err}", screenshot.display())
+                            })
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    let scan = scan_inventory(&images, None, &database);
+                    let changes = inventory.part_changes(&scan.parts);
+                    if apply {
+                        inventory.apply_part_changes(&changes);
+                        inventory.save(&path)?;
+                    }
+                    let report = ScanReport {
+                        changes,
+                        unknown: scan.unknown,
+                        applied: apply,
+                    };
+                    print_scan_report(&report, format);
+                }
+                InventoryAction::Sell => {
+                    let plan = plan_sales(&database, &inventory.parts)?;
+                    print_sale_plan(&plan, format);
+                }
+                InventoryAction::Ducats {
+                    rate,
+                    keep,
+                    trade_set_parts,
+                    target,
+                } => {
+                    let options = TradeInOptions {
+                        platinum_per_ducat: rate.unwrap_or(arguments.ducat_rate),
+                        keep,
+                        complete_sets: !trade_set_parts,
+                        target_ducats: target,
+                    };
+                    let plan = plan_trade_ins(&database, &inventory.parts, &options)?;
+                    print_trade_in_plan(&plan, format);
+                }
+            }
+        }
+        Command::History {
+            from,
+            to,
+            tier,
+            refinement,
+            relic_count,
+            top,
+            list,
+            record,
+        } => {
+            let history = PriceHistory::default();
+            if record {
+                let snapshot = history.record(&prices)?;
+                eprintln!("Stored the prices as {}", snapshot.path.display());
+            }
+            if list {
+                for snapshot in history.snapshots()? {
+                    println!("{}  {}", snapshot.date, snapshot.path.display());
+                }
+                return Ok(());
+            }
+            let options = HistoryOptions {
+                from,
+                to,
+                tiers: if tier.is_empty() {
+                    RelicTier::ALL.to_vec()
+                } else {
+                    tier
+                },
+                refinement,
+                relic_count,
+                top,
+                valuation,
+            };
+            let report = history_report(&history, &filtered_items, &options)?;
+            print_history_report(&report, format);
+        }
+        Command::Sets { filter, vaulted } => {
+            let rows = set_rows(&database, filter.as_deref(), vaulted);
+            print_set_rows(&rows, format);
+        }
+        Command::Snap { screenshot, region } => {
+            let image = image::open(&screenshot)
+                .map_err(|err| anyhow!("Failed to open {}: {err}", screenshot.display()))?;
+            let region = region.unwrap_or_else(|| Region::of(&image));
+            if !region.fits(&image) {
+                bail!(
+                    "Region does not fit the {}x{} screenshot",
+                    image.width(),
+                    image.height()
+                );
+            }
+            let rows: Vec<_> = snap_items(&image, region, None, &database)
+                .into_iter()
+                .map(|item| SnapRow {
+                    name: item.name.clone(),
+                    platinum: item.platinum,
+                    ducats: item.ducats,
+                    vaulted: item.vaulted,
+                })
+                .collect();
+            print_snap_rows(&rows, format);
+        }
+        Command::PriceCheck { message } => {
+            if format == OutputFormat::Csv {
+                println!("mention,name,platinum,ducats,vaulted");
+            }
+            if message.is_empty() {
+                for line in io::stdin().lock().lines() {
+                    let line = line?;
+                    print_price_checks(&line, &price_check(&database, &line), format);
+                }
+            } else {
+                let message = message.join(" ");
+                print_price_checks(&message, &price_check(&database, &message), format);
+            }
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,112 @@
+use std::{
+    error::Error,
+    fs::read_to_string,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use image::io::Reader;
+use indexmap::IndexMap;
+use wfinfo::{
+    ocr::{detect_theme, detect_theme_per_pixel},
+    testing::Label,
+    theme::{Theme, ThemeRanking},
+};
+
+/// Times theme detection from the quantised colour histogram against comparing every pixel, on
+/// labeled screenshots, and checks that both pick the same theme with close scores
+#[derive(Parser, Debug)]
+struct Arguments {
+    /// labels.json as written by the image tool, the screenshots are next to it
+    #[arg(default_value = "WFI test images/labels.json")]
+    labels: PathBuf,
+    /// Times every screenshot is detected with each method
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    runs: u32,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let arguments = Arguments::parse();
+    let labels: IndexMap<String, Label> =
+        serde_json::from_str(&read_to_string(&arguments.labels)?)?;
+    let directory = arguments.labels.parent().unwrap_or(&arguments.labels);
+
+    let (mut per_pixel_time, mut histogram_time) = (Duration::ZERO, Duration::ZERO);
+    let (mut screenshots, mut different, mut correct) = (0, 0, 0);
+    let mut max_difference: f32 = 0.0;
+    for (file_name, label) in labels {
+        let path = directory.join(&file_name);
+        let image = match Reader::open(&path).map(|reader| reader.decode()) {
+            Ok(Ok(image)) => image,
+            _ => {
+                println!("Skipping unreadable screenshot {}", path.display());
+                continue;
+            }
+        };
+
+        let start = Instant::now();
+        for _ in 1..arguments.runs {
+            detect_theme_per_pixel(&image);
+        }
+        let per_pixel = detect_theme_per_pixel(&image);
+        let per_pixel_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 1..arguments.runs {
+            detect_theme(&image);
+        }
+        let histogram = detect_theme(&image);
+        let histogram_elapsed = start.elapsed();
+
+        // Largest difference in any theme's score, themes missing from a ranking scored 0
+        let score = |ranking: &ThemeRanking, theme: &Theme| {
+            ranking
+                .0
+                .iter()
+                .find(|(ranked, _)| ranked == theme)
+                .map_or(0.0, |(_, score)| *score)
+        };
+        let difference = per_pixel
+            .0
+            .iter()
+            .chain(&histogram.0)
+            .map(|(theme, _)| (score(&per_pixel, theme) - score(&histogram, theme)).abs())
+            .fold(0.0, f32::max);
+        let same = per_pixel.best() == histogram.best();
+        println!(
+            "{file_name}: {} in {:?} per pixel, {} in {:?} from the histogram, scores differ by \
+             at most {difference:.4}{}",
+            per_pixel.best().name,
+            per_pixel_elapsed / arguments.runs,
+            histogram.best().name,
+            histogram_elapsed / arguments.runs,
+            if same { "" } else { ", BEST THEMES DIFFER" }
+        );
+
+        screenshots += 1;
+        per_pixel_time += per_pixel_elapsed;
+        histogram_time += histogram_elapsed;
+        different += usize::from(!same);
+        max_difference = max_difference.max(difference);
+        correct += usize::from(*histogram.best() == label.theme);
+    }
+
+    if screenshots == 0 {
+        return Err("No screenshots could be read".into());
+    }
+    println!(
+        "{screenshots} screenshots: {:?} per pixel, {:?} from the histogram, {:.1}x faster",
+        per_pixel_time / (screenshots * arguments.runs),
+        histogram_time / (screenshots * arguments.runs),
+        per_pixel_time.as_secs_f32() / histogram_time.as_secs_f32()
+    );
+    println!(
+        "{correct}/{screenshots} detected as labeled, {different} with a different best theme, \
+         scores differ by at most {max_difference:.4}"
+    );
+    if different > 0 {
+        return Err("The best themes differ".into());
+    }
+    Ok(())
+}
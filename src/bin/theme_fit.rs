@@ -0,0 +1,101 @@
+use std::{collections::BTreeMap, error::Error, fs::read_to_string, path::PathBuf};
+
+use clap::Parser;
+use image::io::Reader;
+use indexmap::IndexMap;
+use wfinfo::{
+    testing::Label,
+    theme::{save_user_theme, user_themes_path, Theme},
+    theme_fit::{fit_range, theme_accuracy},
+    utils::DataArguments,
+};
+
+/// Fits the text filter of every labeled theme so that as many items as possible are read
+/// correctly from its screenshots. Every evaluation reads all of a theme's screenshots, so this
+/// takes a while.
+#[derive(Parser, Debug)]
+struct Arguments {
+    /// labels.json as written by the image tool, the screenshots are next to it
+    #[arg(default_value = "WFI test images/labels.json")]
+    labels: PathBuf,
+    /// Only fit these themes
+    #[arg(long)]
+    theme: Vec<String>,
+    /// Most filters tried per theme
+    #[arg(long, default_value_t = 200)]
+    max_evaluations: usize,
+    /// Theme file the improved themes are written to, by default the user's themes.json
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Only report the accuracy, without writing anything
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    data: DataArguments,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+    let arguments = Arguments::parse();
+    let database = arguments.data.load_database()?;
+    let output = arguments.output.unwrap_or_else(user_themes_path);
+
+    let labels: IndexMap<String, Label> =
+        serde_json::from_str(&read_to_string(&arguments.labels)?)?;
+    let directory = arguments.labels.parent().unwrap_or(&arguments.labels);
+
+    let mut screenshots_by_theme = BTreeMap::new();
+    for (file_name, label) in labels {
+        if !arguments.theme.is_empty() && !arguments.theme.contains(&label.theme.name) {
+            continue;
+        }
+        let path = directory.join(&file_name);
+        let image = match Reader::open(&path).map(|reader| reader.decode()) {
+            Ok(Ok(image)) => image,
+            _ => {
+                println!("Skipping unreadable screenshot {}", path.display());
+                continue;
+            }
+        };
+        screenshots_by_theme
+            .entry(label.theme.name.clone())
+            .or_insert_with(Vec::new)
+            .push((image, label));
+    }
+
+    for (name, screenshots) in screenshots_by_theme {
+        let theme = Theme::named(&name).expect("Labels only name loaded themes");
+        let before = theme_accuracy(&database, &screenshots, &theme);
+        println!(
+            "{name}: {before} correct in {} screenshots",
+            screenshots.len()
+        );
+
+        let (filter, _) = fit_range(
+            theme.approximate_filter(),
+            arguments.max_evaluations,
+            |filter| {
+                let candidate = Theme {
+                    filter: Some(filter.clone()),
+                    ..theme.clone()
+                };
+                theme_accuracy(&database, &screenshots, &candidate).ratio()
+            },
+        );
+        let fitted = Theme {
+            filter: Some(filter),
+            ..theme
+        };
+        let after = theme_accuracy(&database, &screenshots, &fitted);
+        println!("{name}: {after} correct with {:?}", fitted.filter);
+
+        if after.correct <= before.correct {
+            println!("{name}: kept, the fitted filter is no better");
+        } else if !arguments.dry_run {
+            save_user_theme(&output, fitted)?;
+            println!("{name}: saved to {}", output.display());
+        }
+    }
+
+    Ok(())
+}
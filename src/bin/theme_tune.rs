@@ -16,7 +16,10 @@ use palette::{FromColor, Hsl, Srgb};
 use wfinfo::{
     database::Database,
     ocr::{self, normalize_string},
-    theme::{HslRange, Theme},
+    theme::{
+        load_user_themes, merge_themes, save_user_theme, user_themes_path, HslRange, Theme,
+        DEFAULT_THEMES,
+    },
 };
 
 fn main() {
@@ -38,6 +41,17 @@ struct MyApp {
     ocr_result: Option<Vec<(String, String)>>,
 
     settings: HslRange<f32>,
+
+    /// Themes the tuning can start from
+    themes: Vec<Theme>,
+    /// The theme the tuning started from, whose colours are kept when saving
+    base: Option<Theme>,
+    theme_name: String,
+    /// Names of the built-in themes, which are only replaced after pressing Save twice
+    built_in: Vec<String>,
+    /// The built-in theme Save was pressed for once
+    replacing: Option<String>,
+    status: String,
 }
 
 impl Default for MyApp {
@@ -62,6 +76,17 @@ impl Default for MyApp {
             ocr_result: None,
 
             settings,
+
+            themes: Theme::iter().cloned().collect(),
+            base: None,
+            theme_name: "Custom".to_string(),
+            built_in: serde_json::from_str::<Vec<Theme>>(DEFAULT_THEMES)
+                .expect("Invalid built-in themes")
+                .into_iter()
+                .map(|theme| theme.name)
+                .collect(),
+            replacing: None,
+            status: String::new(),
         }
     }
 }
@@ -78,7 +103,7 @@ fn spawn_ocr_thread(
     let images = images.to_owned();
 
     thread::spawn(move || {
-        let database = Database::load_from_file(None, None);
+        let database = Database::load_from_file(None, None).unwrap();
         loop {
             let (mut index, mut last_request): (usize, HslRange<f32>) =
                 request_receiver.recv().unwrap();
@@ -92,7 +117,7 @@ fn spawn_ocr_thread(
             let image = &images[index];
             let strings = ocr::reward_image_to_reward_names(
                 image.clone(),
-                Some(Theme::Custom(last_request.to_ordered())),
+                Some(Theme::custom(last_request.to_ordered())),
             );
             let results = strings
                 .iter()
@@ -162,6 +187,40 @@ impl eframe::App for MyApp {
                 ui.spinner();
             }
         });
+        egui::TopBottomPanel::top("Theme Panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let mut start_from = None;
+                egui::ComboBox::from_label("Start from")
+                    .selected_text("Theme")
+                    .show_ui(ui, |ui| {
+                        for theme in self.themes.iter() {
+                            if ui.selectable_label(false, theme.name.as_str()).clicked() {
+                                start_from = Some(theme.clone());
+                            }
+                        }
+                    });
+                if let Some(theme) = start_from {
+                    self.settings = theme.approximate_filter().to_unordered();
+                    // Saved under a new name, so the built-in theme stays as it is
+                    self.theme_name = if self.built_in.contains(&theme.name) {
+                        format!("{} (custom)", theme.name)
+                    } else {
+                        theme.name.clone()
+                    };
+                    self.base = Some(theme);
+                    self.refresh();
+                }
+
+                ui.text_edit_singleline(&mut self.theme_name);
+                if ui.button("Save").clicked() {
+                    self.save_theme();
+                }
+                if ui.button("Load").clicked() {
+                    self.load_theme();
+                }
+                ui.label(self.status.as_str());
+            });
+        });
         egui::TopBottomPanel::bottom("Bottom Panel").show(ctx, |ui| {
             if ui
                 .add(
@@ -211,6 +270,73 @@ impl eframe::App for MyApp {
 }
 
 impl MyApp {
+    fn refresh(&mut self) {
+        self.image = None;
+        self.ocr_request_sender
+            .send((self.selected_image_index, self.settings.clone()))
+            .unwrap();
+        self.ocr_result = None;
+    }
+
+    /// Writes the tuned filter as a theme to the user's themes.json, where wfinfo picks it up
+    /// with `--theme <name>`. Only the filter of the theme the tuning started from is replaced.
+    fn save_theme(&mut self) {
+        let filter = self.settings.to_ordered();
+        let theme = Theme {
+            name: self.theme_name.trim().to_string(),
+            ..match &self.base {
+                Some(base) => base.with_filter(filter),
+                None => Theme::custom(filter),
+            }
+        };
+        if theme.name.is_empty() {
+            self.status = "Enter a name for the theme".to_string();
+            return;
+        }
+        if self.built_in.contains(&theme.name) && self.replacing.as_ref() != Some(&theme.name) {
+            self.status = format!(
+                "{} is a built-in theme, press Save again to replace it",
+                theme.name
+            );
+            self.replacing = Some(theme.name);
+            return;
+        }
+        self.replacing = None;
+        let path = user_themes_path();
+        match save_user_theme(&path, theme.clone()) {
+            Ok(()) => {
+                self.status = format!("Saved {} to {}", theme.name, path.display());
+                merge_themes(&mut self.themes, vec![theme]);
+            }
+            Err(err) => self.status = format!("Failed to save {}: {err}", path.display()),
+        }
+    }
+
+    /// Loads the filter of the theme with the entered name from the user's themes.json.
+    fn load_theme(&mut self) {
+        let path = user_themes_path();
+        let theme = match load_user_themes(&path) {
+            Ok(themes) => themes
+                .into_iter()
+                .find(|theme| theme.name == self.theme_name.trim()),
+            Err(err) => {
+                self.status = format!("Failed to load {}: {err}", path.display());
+                return;
+            }
+        };
+        match theme {
+            Some(theme) => {
+                self.settings = theme.approximate_filter().to_unordered();
+                self.status = format!("Loaded {}", theme.name);
+                self.base = Some(theme);
+                self.refresh();
+            }
+            None => {
+                self.status = format!("No theme named {} in {}", self.theme_name, path.display())
+            }
+        }
+    }
+
     fn process_image(&self, image: &DynamicImage) -> DynamicImage {
         const PIXEL_REWARD_WIDTH: f32 = 968.0;
         const PIXEL_REWARD_HEIGHT: f32 = 235.0;
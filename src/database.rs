@@ -1,13 +1,20 @@
-use std::{collections::HashMap, fs::read_to_string, path::Path};
+use std::{collections::HashMap, fmt, fs::read_to_string, path::Path};
 
+use anyhow::Context;
+use fraction::BigFraction;
 use levenshtein::levenshtein;
-use serde::Deserialize;
+use log::warn;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::{
-    statistics::{self, Bucket},
+    ocr::normalize_string,
+    statistics::{self, Bucket, Distribution, ExactBucket},
     wfinfo_data::{
-        item_data::{EquipmentType, FilteredItems, Refinement, Relic, Relics},
+        item_data::{
+            DropChances, EquipmentType, FilteredItems, Rarity, Refinement, Relic, RelicDrop,
+            RelicId, Relics,
+        },
         price_data::PriceItem,
     },
 };
@@ -16,6 +23,15 @@ use crate::{
 pub struct Database {
     items: Vec<Item>,
     pub relics: Relics,
+    #[serde(skip)]
+    pub drop_chances: DropChances,
+    #[serde(skip)]
+    relic_sources: HashMap<String, Vec<RelicSource>>,
+    /// Market price of intact relics, keyed like "Lith A1 Relic".
+    #[serde(skip)]
+    relic_prices: HashMap<String, f32>,
+    #[serde(skip)]
+    sets: Vec<PrimeSet>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -24,26 +40,164 @@ pub struct Item {
     pub drop_name: String,
     pub platinum: f32,
     pub ducats: usize,
+    pub vaulted: bool,
+    #[serde(default)]
+    pub kind: RewardKind,
+}
+
+/// What a reward is. Only prime parts are traded, the other kinds are the ignored items of the
+/// reward screen and are worth nothing.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RewardKind {
+    #[default]
+    PrimePart,
+    Forma,
+    Riven,
+    Credits,
+    Resource,
+}
+
+impl RewardKind {
+    /// The kind of an ignored item, by its name.
+    pub fn of_ignored(name: &str) -> RewardKind {
+        if name.starts_with("Forma") {
+            RewardKind::Forma
+        } else if name.starts_with("Riven") {
+            RewardKind::Riven
+        } else if name == "Credits" {
+            RewardKind::Credits
+        } else {
+            RewardKind::Resource
+        }
+    }
+}
+
+/// An item on the reward screen and how many of it are rewarded, e.g. "200 X Kuva".
+#[derive(Clone, Debug)]
+pub struct Reward {
+    pub item: Item,
+    pub count: u32,
+}
+
+impl Reward {
+    pub fn value(&self, valuation: Valuation) -> f32 {
+        valuation.value(&self.item) * self.count as f32
+    }
+}
+
+impl fmt::Display for Reward {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count > 1 {
+            write!(f, "{} x{}", self.item.name, self.count)
+        } else {
+            write!(f, "{}", self.item.name)
+        }
+    }
+}
+
+/// Splits the stack size off a reward name read from the screen, like the "200 X" of
+/// "200 X Kuva" or the "15,000" of "15,000 Credits".
+fn split_reward_count(text: &str) -> (u32, &str) {
+    let text = text.trim();
+    let name = text.trim_start_matches(|c: char| c.is_ascii_digit() || c == ',' || c == '.');
+    let count = text[..text.len() - name.len()]
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .unwrap_or(1);
+    let name = name.trim_start();
+    let name = match name.strip_prefix(['X', 'x']) {
+        Some(rest) if count > 1 && rest.starts_with(char::is_whitespace) => rest,
+        _ => name,
+    };
+    (count, name)
+}
+
+/// A tradeable set of prime parts, e.g. "Titania Prime Set".
+#[derive(Clone, Debug, Serialize)]
+pub struct PrimeSet {
+    pub name: String,
+    pub platinum: f32,
+    pub vaulted: bool,
+    pub parts: Vec<SetPart>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SetPart {
+    pub name: String,
+    /// How many of the part one set needs.
+    pub count: u32,
+}
+
+/// A relic that drops a given item, and in which rarity slot.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RelicSource {
+    #[serde(flatten)]
+    pub relic: RelicId,
+    pub rarity: Rarity,
+    pub vaulted: bool,
+}
+
+/// How an item is valued when comparing relic rewards.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Valuation {
+    Platinum,
+    Ducats,
+    /// The higher of the platinum price and the ducats converted at the given platinum per ducat.
+    Blend {
+        platinum_per_ducat: f32,
+    },
+}
+
+impl Valuation {
+    pub fn value(&self, item: &Item) -> f32 {
+        if item.kind != RewardKind::PrimePart {
+            return 0.0;
+        }
+        match self {
+            Valuation::Platinum => item.platinum,
+            Valuation::Ducats => item.ducats as f32,
+            Valuation::Blend { platinum_per_ducat } => {
+                item.platinum.max(item.ducats as f32 * platinum_per_ducat)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RelicSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?})", self.relic, self.rarity)
+    }
 }
 
 impl Database {
-    pub fn load_from_file(prices: Option<&Path>, filtered_items: Option<&Path>) -> Database {
+    pub fn load_from_file(
+        prices: Option<&Path>,
+        filtered_items: Option<&Path>,
+    ) -> Result<Database, anyhow::Error> {
         // download file from: https://api.warframestat.us/wfinfo/prices
-        let text = read_to_string(prices.unwrap_or_else(|| Path::new("prices.json"))).unwrap();
-        let price_list: Vec<PriceItem> = serde_json::from_str(&text).unwrap();
+        let prices_path = prices.unwrap_or_else(|| Path::new("prices.json"));
+        let text = read_to_string(prices_path)
+            .with_context(|| format!("Failed to read {}", prices_path.display()))?;
+        let price_list: Vec<PriceItem> = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid {}", prices_path.display()))?;
         let price_table: HashMap<String, f32> = price_list
             .into_iter()
             .map(|item| (item.name, item.custom_avg))
             .collect();
 
-        let text =
-            read_to_string(filtered_items.unwrap_or_else(|| Path::new("filtered_items.json")))
-                .unwrap();
-        let mut json = serde_json::from_str(&text).unwrap();
+        let filtered_items_path =
+            filtered_items.unwrap_or_else(|| Path::new("filtered_items.json"));
+        let text = read_to_string(filtered_items_path)
+            .with_context(|| format!("Failed to read {}", filtered_items_path.display()))?;
+        let mut json = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid {}", filtered_items_path.display()))?;
 
         remove_empty_relics_from_json(&mut json);
 
-        let filtered_items: FilteredItems = serde_json::from_value(json).unwrap();
+        let filtered_items: FilteredItems = serde_json::from_value(json)
+            .with_context(|| format!("Invalid {}", filtered_items_path.display()))?;
 
         let mut items: Vec<_> = filtered_items
             .eqmt
@@ -72,7 +226,7 @@ impl Database {
                         {
                             Some(plat) => plat,
                             None => {
-                                println!("Failed to find price for item: {name}");
+                                warn!("Failed to find price for item: {name}");
                                 return None;
                             }
                         };
@@ -83,24 +237,97 @@ impl Database {
                             drop_name,
                             platinum,
                             ducats,
+                            vaulted: equipment_item.vaulted,
+                            kind: RewardKind::PrimePart,
                         })
                     })
             })
-            .chain(filtered_items.ignored_items.keys().map(|name| Item {
-                name: name.to_owned(),
-                drop_name: name.to_owned(),
-                platinum: 0.0,
-                ducats: 0,
-            }))
+            .chain(
+                filtered_items
+                    .ignored_items
+                    .keys()
+                    .map(String::as_str)
+                    // Void storms also reward credits, which are missing from the ignored items
+                    .chain(
+                        Some("Credits")
+                            .filter(|name| !filtered_items.ignored_items.contains_key(*name)),
+                    )
+                    .map(|name| Item {
+                        name: name.to_owned(),
+                        drop_name: name.to_owned(),
+                        platinum: 0.0,
+                        ducats: 0,
+                        vaulted: false,
+                        kind: RewardKind::of_ignored(name),
+                    }),
+            )
             .collect();
 
         if let Some(item) = items.iter_mut().find(|item| item.name == "Forma Blueprint") {
             item.platinum = 0.0;
         };
 
-        let relics = filtered_items.relics;
+        let mut relics = filtered_items.relics;
 
-        Database { items, relics }
+        // Optional, the built-in chances are used when the file does not exist
+        let drop_chances_path = filtered_items_path.with_file_name("drop_chances.json");
+        let drop_chances: DropChances = match read_to_string(&drop_chances_path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Invalid {}", drop_chances_path.display()))?,
+            Err(_) => DropChances::default(),
+        };
+        drop_chances
+            .apply_overrides(&mut relics)
+            .with_context(|| format!("Invalid {}", drop_chances_path.display()))?;
+        // A relic whose chances don't add up would skew every value computed from it
+        relics.retain(|id, relic| match drop_chances.validate_relic(relic) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("Ignoring relic {id}: {err}");
+                false
+            }
+        });
+
+        let mut sets: Vec<_> = filtered_items
+            .eqmt
+            .iter()
+            .filter_map(|(name, equipment_item)| {
+                let name = format!("{name} Set");
+                let platinum = *price_table.get(&name)?;
+                let mut parts: Vec<_> = equipment_item
+                    .parts
+                    .iter()
+                    .filter(|(part_name, _)| !part_name.ends_with(" Set"))
+                    .map(|(part_name, part)| SetPart {
+                        name: part_name.to_owned(),
+                        count: part.count,
+                    })
+                    .collect();
+                parts.sort_by(|a, b| a.name.cmp(&b.name));
+                Some(PrimeSet {
+                    name,
+                    platinum,
+                    vaulted: equipment_item.vaulted,
+                    parts,
+                })
+            })
+            .collect();
+        sets.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let relic_sources = index_relic_sources(&relics);
+        let relic_prices = price_table
+            .into_iter()
+            .filter(|(name, _)| name.ends_with(" Relic"))
+            .collect();
+
+        Ok(Database {
+            items,
+            relics,
+            drop_chances,
+            relic_sources,
+            relic_prices,
+            sets,
+        })
     }
 
     pub fn find_item(&self, needle: &str, threshold: Option<usize>) -> Option<&Item> {
@@ -121,68 +348,144 @@ impl Database {
         })
     }
 
+    /// Matches a reward name read from the reward screen, with its stack size. `None` for empty
+    /// slots and unknown rewards.
+    pub fn find_reward(&self, text: &str) -> Option<Reward> {
+        let (count, name) = split_reward_count(text);
+        let name = normalize_string(name);
+        if name.is_empty() {
+            return None;
+        }
+        let item = self.find_item(&name, None)?;
+        Some(Reward {
+            item: item.clone(),
+            count: if item.kind == RewardKind::PrimePart {
+                1
+            } else {
+                count
+            },
+        })
+    }
+
     pub fn find_item_exact(&self, needle: &str) -> Option<&Item> {
         self.items.iter().find(|item| item.name == needle)
     }
 
-    fn relic_to_bucket(&self, relic: &Relic, refinement: Refinement) -> Bucket {
-        let common_chance = refinement.common_chance();
-        let uncommon_chance = refinement.uncommon_chance();
-        let rare_chance = refinement.rare_chance();
+    /// Lists the relics that drop the item with the given exact name, ordered by tier and relic
+    /// name.
+    pub fn relic_sources(&self, item_name: &str) -> &[RelicSource] {
+        self.relic_sources
+            .get(item_name)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
 
-        let item_names = [
-            (&relic.common1, common_chance),
-            (&relic.common2, common_chance),
-            (&relic.common3, common_chance),
-            (&relic.uncommon1, uncommon_chance),
-            (&relic.uncommon2, uncommon_chance),
-            (&relic.rare1, rare_chance),
-        ];
-        let items = item_names
+    /// Matches an item name, exactly ignoring case or else fuzzily like OCR output, and lists
+    /// every relic that drops it.
+    pub fn find_relics_dropping(&self, needle: &str) -> Option<(&Item, &[RelicSource])> {
+        let item = self.lookup_item(needle)?;
+        Some((item, self.relic_sources(&item.name)))
+    }
+
+    /// Matches an item name typed by the user, exactly ignoring case or else fuzzily.
+    pub fn lookup_item(&self, needle: &str) -> Option<&Item> {
+        self.items
+            .iter()
+            .find(|item| item.name.eq_ignore_ascii_case(needle.trim()))
+            .or_else(|| self.find_item(needle, None))
+    }
+
+    pub fn sets(&self) -> &[PrimeSet] {
+        &self.sets
+    }
+
+    /// Finds a set by name ignoring case, with or without the trailing "Set".
+    pub fn find_set(&self, name: &str) -> Option<&PrimeSet> {
+        let name = name.trim();
+        self.sets.iter().find(|set| {
+            set.name.eq_ignore_ascii_case(name)
+                || set
+                    .name
+                    .strip_suffix(" Set")
+                    .is_some_and(|equipment| equipment.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// The set the part belongs to.
+    pub fn set_of_part(&self, part_name: &str) -> Option<&PrimeSet> {
+        self.sets
+            .iter()
+            .find(|set| set.parts.iter().any(|part| part.name == part_name))
+    }
+
+    /// Platinum for selling all the parts of a set individually, or `None` if a part has no price.
+    pub fn parts_platinum(&self, set: &PrimeSet) -> Option<f32> {
+        set.parts.iter().try_fold(0.0, |total, part| {
+            let item = self.find_item_exact(&part.name)?;
+            Some(total + item.platinum * part.count as f32)
+        })
+    }
+
+    /// Market price of an intact relic, if the price data lists one.
+    pub fn relic_price(&self, relic: &RelicId) -> Option<f32> {
+        self.relic_prices.get(&format!("{relic} Relic")).copied()
+    }
+
+    /// Looks up the database entries for the drops of a relic, paired with the drop.
+    pub fn relic_items<'a>(&'a self, relic: &'a Relic) -> Vec<(&'a Item, &'a RelicDrop)> {
+        relic
+            .rewards
+            .iter()
+            .map(|drop| {
+                (
+                    self.find_item_exact(&drop.item)
+                        .unwrap_or_else(|| panic!("Failed to find item {} in database", drop.item)),
+                    drop,
+                )
+            })
+            .collect()
+    }
+
+    fn relic_to_bucket(
+        &self,
+        relic: &Relic,
+        refinement: Refinement,
+        valuation: Valuation,
+    ) -> Bucket {
+        let items = self
+            .relic_items(relic)
             .into_iter()
-            .map(|(name, chance)| statistics::Item {
-                value: self
-                    .find_item_exact(name)
-                    .unwrap_or_else(|| panic!("Failed to find item {} in database", name))
-                    .platinum,
-                probability: chance,
+            .map(|(item, drop)| statistics::Item {
+                value: valuation.value(item),
+                probability: self.drop_chances.drop_chance(drop, refinement),
             })
             .collect();
         Bucket::new(items)
     }
 
     pub fn single_relic_value(&self, relic: &Relic, refinement: Refinement) -> f32 {
-        let common_chance = refinement.common_chance();
-        let uncommon_chance = refinement.uncommon_chance();
-        let rare_chance = refinement.rare_chance();
-
-        let value = 0.0
-            + self.find_item_exact(&relic.common1).unwrap().platinum * common_chance
-            + self.find_item_exact(&relic.common2).unwrap().platinum * common_chance
-            + self.find_item_exact(&relic.common3).unwrap().platinum * common_chance
-            + self.find_item_exact(&relic.uncommon1).unwrap().platinum * uncommon_chance
-            + self.find_item_exact(&relic.uncommon2).unwrap().platinum * uncommon_chance
-            + self.find_item_exact(&relic.rare1).unwrap().platinum * rare_chance;
-
-        let item_names = [
-            (&relic.common1, common_chance),
-            (&relic.common2, common_chance),
-            (&relic.common3, common_chance),
-            (&relic.uncommon1, uncommon_chance),
-            (&relic.uncommon2, uncommon_chance),
-            (&relic.rare1, rare_chance),
-        ];
-        let value2: f32 = item_names
+        self.relic_items(relic)
             .into_iter()
-            .map(|(name, chance)| {
-                let plat = self.find_item_exact(name).unwrap().platinum;
-                println!("{plat} * {chance}");
-                plat * chance
+            .fold(0.0, |value, (item, drop)| {
+                value + item.platinum * self.drop_chances.drop_chance(drop, refinement)
             })
-            .sum();
-        println!("{value} vs {value2}");
+    }
 
-        value
+    /// Chance that at least one of `number_of_relics` copies of the relic drops a rare item.
+    pub fn any_rare_chance(
+        &self,
+        relic: &Relic,
+        refinement: Refinement,
+        number_of_relics: u32,
+    ) -> f32 {
+        let rare_chance = relic
+            .rewards
+            .iter()
+            .filter(|drop| drop.rarity == Rarity::Rare)
+            .fold(0.0, |total, drop| {
+                total + self.drop_chances.drop_chance(drop, refinement)
+            });
+        1.0 - (1.0 - rare_chance).powi(number_of_relics as i32)
     }
 
     pub fn shared_relic_value(
@@ -191,28 +494,126 @@ impl Database {
         refinement: Refinement,
         number_of_relics: u32,
     ) -> f32 {
-        let bucket = self.relic_to_bucket(relic, refinement);
+        self.shared_relic_value_in(relic, refinement, number_of_relics, Valuation::Platinum)
+    }
+
+    /// Like [`Database::shared_relic_value`], with every item valued according to `valuation`.
+    pub fn shared_relic_value_in(
+        &self,
+        relic: &Relic,
+        refinement: Refinement,
+        number_of_relics: u32,
+        valuation: Valuation,
+    ) -> f32 {
+        let bucket = self.relic_to_bucket(relic, refinement, valuation);
         bucket.expectation_of_best_of_n(number_of_relics)
     }
 
+    /// Like [`Database::shared_relic_value_in`], computed with exact rational arithmetic from the
+    /// decimal prices and drop chances.
+    pub fn shared_relic_value_exact(
+        &self,
+        relic: &Relic,
+        refinement: Refinement,
+        number_of_relics: u32,
+        valuation: Valuation,
+    ) -> BigFraction {
+        ExactBucket::from(&self.relic_to_bucket(relic, refinement, valuation))
+            .expectation_of_best_of_n(number_of_relics)
+    }
+
+    /// Full distribution of the best reward's value when `number_of_relics` copies of the relic
+    /// are cracked at the same refinement.
+    pub fn shared_relic_distribution(
+        &self,
+        relic: &Relic,
+        refinement: Refinement,
+        number_of_relics: u32,
+        valuation: Valuation,
+    ) -> Distribution {
+        self.relic_to_bucket(relic, refinement, valuation)
+            .distribution_of_best_of_n(number_of_relics)
+    }
+
+    /// Expected value of the best reward when each squad member cracks a copy of the relic at
+    /// their own refinement.
+    pub fn mixed_relic_value(&self, relic: &Relic, refinements: &[Refinement]) -> f32 {
+        self.mixed_relic_value_in(relic, refinements, Valuation::Platinum)
+    }
+
+    pub fn mixed_relic_value_in(
+        &self,
+        relic: &Relic,
+        refinements: &[Refinement],
+        valuation: Valuation,
+    ) -> f32 {
+        let buckets: Vec<_> = refinements
+            .iter()
+            .map(|&refinement| self.relic_to_bucket(relic, refinement, valuation))
+            .collect();
+        Bucket::expectation_of_best_of_each(&buckets)
+    }
+
+    pub fn mixed_relic_value_exact(
+        &self,
+        relic: &Relic,
+        refinements: &[Refinement],
+        valuation: Valuation,
+    ) -> BigFraction {
+        let buckets: Vec<_> = refinements
+            .iter()
+            .map(|&refinement| {
+                ExactBucket::from(&self.relic_to_bucket(relic, refinement, valuation))
+            })
+            .collect();
+        ExactBucket::expectation_of_best_of_each(&buckets)
+    }
+
+    /// Expected value of the best reward when each squad member cracks their own relic at their
+    /// own refinement.
+    pub fn squad_relic_value_in(
+        &self,
+        squad: &[(&Relic, Refinement)],
+        valuation: Valuation,
+    ) -> f32 {
+        let buckets: Vec<_> = squad
+            .iter()
+            .map(|&(relic, refinement)| self.relic_to_bucket(relic, refinement, valuation))
+            .collect();
+        Bucket::expectation_of_best_of_each(&buckets)
+    }
+
+    pub fn mixed_relic_value_bruteforce(&self, relic: &Relic, refinements: &[Refinement]) -> f32 {
+        let items = self.relic_items(relic);
+
+        let mut value = 0.0;
+        for combination in 0..items.len().pow(refinements.len() as u32) {
+            let mut remaining = combination;
+            let mut best: f32 = 0.0;
+            let mut probability = 1.0;
+            for refinement in refinements {
+                let (item, drop) = items[remaining % items.len()];
+                remaining /= items.len();
+                best = best.max(item.platinum);
+                probability *= self.drop_chances.drop_chance(drop, *refinement);
+            }
+            value += best * probability;
+        }
+
+        value
+    }
+
     pub fn shared_relic_value_bruteforce(
         &self,
         relic: &Relic,
         refinement: Refinement,
         _number_of_relics: u32,
     ) -> f32 {
-        let common_chance = refinement.common_chance();
-        let uncommon_chance = refinement.uncommon_chance();
-        let rare_chance = refinement.rare_chance();
-
-        let items = [
-            (&relic.common1, common_chance),
-            (&relic.common2, common_chance),
-            (&relic.common3, common_chance),
-            (&relic.uncommon1, uncommon_chance),
-            (&relic.uncommon2, uncommon_chance),
-            (&relic.rare1, rare_chance),
-        ];
+        let items: Vec<_> = self
+            .relic_items(relic)
+            .into_iter()
+            .map(|(item, drop)| (item, self.drop_chances.drop_chance(drop, refinement)))
+            .collect();
 
         let mut value = 0.0;
         for item1 in items.iter() {
@@ -221,7 +622,7 @@ impl Database {
                     for item4 in items.iter() {
                         value += [item1.0, item2.0, item3.0, item4.0]
                             .iter()
-                            .map(|name| self.find_item_exact(name).unwrap().platinum)
+                            .map(|item| item.platinum)
                             .max_by(|a, b| a.total_cmp(b))
                             .unwrap()
                             * item1.1
@@ -237,6 +638,25 @@ impl Database {
     }
 }
 
+fn index_relic_sources(relics: &Relics) -> HashMap<String, Vec<RelicSource>> {
+    let mut sources: HashMap<String, Vec<RelicSource>> = HashMap::new();
+    let mut relics: Vec<_> = relics.iter().collect();
+    relics.sort_by(|a, b| a.0.cmp(&b.0));
+    for (id, relic) in relics {
+        for (item_name, rarity) in relic.drops() {
+            sources
+                .entry(item_name.to_owned())
+                .or_default()
+                .push(RelicSource {
+                    relic: id.clone(),
+                    rarity,
+                    vaulted: relic.vaulted,
+                });
+        }
+    }
+    sources
+}
+
 fn remove_empty_relics_from_json(value: &mut Value) {
     let relics = &mut value["relics"];
     for (_, kind) in relics.as_object_mut().unwrap() {
@@ -249,17 +669,48 @@ fn remove_empty_relics_from_json(value: &mut Value) {
 #[cfg(test)]
 mod test {
     use approx::assert_relative_eq;
+    use fraction::ToPrimitive;
 
     use super::*;
+    use crate::wfinfo_data::item_data::RelicTier;
 
     #[test]
     pub fn can_load_database() {
-        Database::load_from_file(None, None);
+        Database::load_from_file(None, None).unwrap();
+    }
+
+    #[test]
+    fn can_parse_relic_ids() {
+        let id: RelicId = "axi a1 relic".parse().unwrap();
+        assert_eq!(id, RelicId::new(RelicTier::Axi, "A1"));
+        assert_eq!(id.to_string().parse::<RelicId>().unwrap(), id);
+        assert!("Void A1".parse::<RelicId>().is_err());
+        assert!("Axi".parse::<RelicId>().is_err());
+
+        let database = Database::load_from_file(None, None).unwrap();
+        for id in database.relics.ids() {
+            assert!(database.relics.get(&id).is_some());
+            let tier = id.tier.to_string().to_uppercase().replace('I', "1");
+            let ocr = format!("{tier} {} RELIC [Radiant]", id.name);
+            assert_eq!(RelicId::from_ocr(&ocr, &database.relics), Some(id));
+        }
+        assert_eq!(RelicId::from_ocr("Forma Blueprint", &database.relics), None);
+        assert_eq!(
+            RelicId::from_ocr("NEO N1 RELIC [FLAWLES5]", &database.relics),
+            Some(RelicId::new(RelicTier::Neo, "N1"))
+        );
+        // As close to Axi A1 as to Axi A2
+        assert_eq!(RelicId::from_ocr("AXI A RELIC", &database.relics), None);
+        assert_eq!(
+            Refinement::from_ocr("NEO N2 RELIC [FLAWLES5]"),
+            Refinement::Flawless
+        );
+        assert_eq!(Refinement::from_ocr("NEO N2 RELIC"), Refinement::Intact);
     }
 
     #[test]
     pub fn can_find_items() {
-        let db = Database::load_from_file(None, None);
+        let db = Database::load_from_file(None, None).unwrap();
 
         let item = db
             .find_item("TitaniaPrimeBlueprint", Some(0))
@@ -274,7 +725,7 @@ mod test {
 
     #[test]
     pub fn can_find_fuzzy_items() {
-        let db = Database::load_from_file(None, None);
+        let db = Database::load_from_file(None, None).unwrap();
 
         let item = db
             .find_item("Akstlett Prlme Recver", None)
@@ -292,36 +743,375 @@ mod test {
         assert_eq!(item.name, "Octavia Prime Systems");
     }
 
+    #[test]
+    fn can_find_non_prime_rewards() {
+        let db = Database::load_from_file(None, None).unwrap();
+
+        let kuva = db.find_reward("200 X Kuva").expect("Failed to find Kuva");
+        assert_eq!(kuva.item.kind, RewardKind::Resource);
+        assert_eq!(kuva.to_string(), "Kuva x200");
+        assert_eq!(kuva.value(Valuation::Platinum), 0.0);
+
+        let forma = db.find_reward("2 X Forma Blueprint").unwrap();
+        assert_eq!(forma.item.kind, RewardKind::Forma);
+        assert_eq!(forma.count, 2);
+        let credits = db.find_reward("15,000 Credits").unwrap();
+        assert_eq!(credits.item.kind, RewardKind::Credits);
+        assert_eq!(credits.to_string(), "Credits x15000");
+        assert_eq!(
+            db.find_reward("Riven Sliver").unwrap().item.kind,
+            RewardKind::Riven
+        );
+
+        let part = db.find_reward("Akstlett Prlme Recver").unwrap();
+        assert_eq!(part.item.kind, RewardKind::PrimePart);
+        assert_eq!(part.to_string(), "Akstiletto Prime Receiver");
+        assert!(db.find_reward(" \n").is_none());
+    }
+
+    #[test]
+    fn relic_sources_match_relic_drops() {
+        let db = Database::load_from_file(None, None).unwrap();
+
+        for (name, relic) in db.relics.axi.iter() {
+            for (item_name, rarity) in relic.drops() {
+                assert!(db
+                    .relic_sources(item_name)
+                    .iter()
+                    .any(|source| source.relic == RelicId::new(RelicTier::Axi, name)
+                        && source.rarity == rarity
+                        && source.vaulted == relic.vaulted));
+            }
+        }
+        assert!(db.relic_sources("Not An Item").is_empty());
+    }
+
+    #[test]
+    fn can_find_relics_dropping_fuzzy_items() {
+        let db = Database::load_from_file(None, None).unwrap();
+
+        let (item, sources) = db
+            .find_relics_dropping("AkstlettPrlmeRecver")
+            .expect("Failed to fuzzy find Akstiletto Prime Receiver in database");
+        assert_eq!(item.name, "Akstiletto Prime Receiver");
+        assert!(!sources.is_empty());
+        for source in sources {
+            let relic = db
+                .relics
+                .get(&source.relic)
+                .expect("Relic source does not exist");
+            assert!(relic
+                .drops()
+                .any(|(name, rarity)| name == &item.name && rarity == source.rarity));
+        }
+    }
+
+    #[test]
+    fn shared_relic_values_match_exact_values() {
+        let database = Database::load_from_file(None, None).unwrap();
+
+        for (_, relic) in database.relics.iter() {
+            for refinement in Refinement::ALL {
+                for number_of_relics in 1..=4 {
+                    assert_relative_eq!(
+                        database.shared_relic_value(relic, refinement, number_of_relics) as f64,
+                        database
+                            .shared_relic_value_exact(
+                                relic,
+                                refinement,
+                                number_of_relics,
+                                Valuation::Platinum
+                            )
+                            .to_f64()
+                            .unwrap(),
+                        epsilon = 0.001
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sets_are_linked_to_their_parts() {
+        let db = Database::load_from_file(None, None).unwrap();
+
+        let set = db
+            .find_set("titania prime")
+            .expect("Failed to find Titania Prime Set in database");
+        assert_eq!(set.name, "Titania Prime Set");
+        assert!(set.parts.iter().all(|part| !part.name.ends_with("Set")));
+        assert_eq!(
+            db.set_of_part("Titania Prime Systems").map(|set| &set.name),
+            Some(&set.name)
+        );
+
+        let parts_platinum: f32 = set
+            .parts
+            .iter()
+            .map(|part| db.find_item_exact(&part.name).unwrap().platinum * part.count as f32)
+            .sum();
+        assert_relative_eq!(db.parts_platinum(set).unwrap(), parts_platinum);
+    }
+
+    #[test]
+    fn drop_chances_must_add_up() {
+        let db = Database::load_from_file(None, None).unwrap();
+        db.drop_chances.validate(&db.relics).unwrap();
+
+        let mut chances = serde_json::to_value(&db.drop_chances).unwrap();
+        chances["Radiant"]["Rare"] = 0.5.into();
+        let chances: DropChances = serde_json::from_value(chances).unwrap();
+        assert!(chances.validate(&db.relics).is_err());
+    }
+
+    #[test]
+    fn drop_chances_can_be_set_per_drop() {
+        let db = Database::load_from_file(None, None).unwrap();
+        let (id, relic) = db.relics.iter().next().unwrap();
+        let common = &relic.rewards[0];
+        let rare = relic.rewards.last().unwrap();
+        let intact = db.drop_chances.chance(Refinement::Intact, Rarity::Common);
+
+        let mut chances = serde_json::to_value(&db.drop_chances).unwrap();
+        chances["relics"][id.to_string()][&common.item]["Intact"] = (intact - 0.05).into();
+        chances["relics"][id.to_string()][&rare.item]["Intact"] =
+            (db.drop_chances.chance(Refinement::Intact, Rarity::Rare) + 0.05).into();
+        let chances: DropChances = serde_json::from_value(chances).unwrap();
+
+        let mut relics = db.relics.clone();
+        chances.apply_overrides(&mut relics).unwrap();
+        let relic = relics.get(&id).unwrap();
+        assert_relative_eq!(
+            chances.drop_chance(&relic.rewards[0], Refinement::Intact),
+            intact - 0.05
+        );
+        assert_relative_eq!(
+            chances.drop_chance(&relic.rewards[0], Refinement::Radiant),
+            chances.chance(Refinement::Radiant, Rarity::Common)
+        );
+        chances.validate(&relics).unwrap();
+
+        let unknown: DropChances = serde_json::from_str(
+            r#"{"relics": {"Axi Z99": {"Forma Blueprint": {"Intact": 0.1}}}}"#,
+        )
+        .unwrap();
+        assert!(unknown.apply_overrides(&mut relics).is_err());
+    }
+
+    #[test]
+    fn relics_with_bad_drop_chances_are_dropped() {
+        let directory =
+            std::env::temp_dir().join(format!("wfinfo-test-drop-chances-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let prices = directory.join("prices.json");
+        let filtered_items = directory.join("filtered_items.json");
+        std::fs::copy("prices.json", &prices).unwrap();
+        std::fs::copy("filtered_items.json", &filtered_items).unwrap();
+
+        let db = Database::load_from_file(Some(&prices), Some(&filtered_items)).unwrap();
+        let (id, relic) = db.relics.iter().next().unwrap();
+        let mut chances = serde_json::to_value(&db.drop_chances).unwrap();
+        chances["relics"][id.to_string()][&relic.rewards[0].item]["Intact"] = 0.9.into();
+        std::fs::write(directory.join("drop_chances.json"), chances.to_string()).unwrap();
+        let pruned = Database::load_from_file(Some(&prices), Some(&filtered_items)).unwrap();
+        assert!(pruned.relics.get(&id).is_none());
+        assert_eq!(pruned.relics.ids().count(), db.relics.ids().count() - 1);
+
+        // Chances the file doesn't list keep their built-in values
+        let common = relic.rewards[0].item.clone();
+        let intact = Refinement::Intact.chance(Rarity::Common);
+        let partial = serde_json::json!({
+            "Radiant": {"Rare": Refinement::Radiant.chance(Rarity::Rare)},
+            "relics": {id.to_string(): {common: {"Intact": intact}}},
+        });
+        std::fs::write(directory.join("drop_chances.json"), partial.to_string()).unwrap();
+        let partial = Database::load_from_file(Some(&prices), Some(&filtered_items)).unwrap();
+        assert_eq!(partial.relics.ids().count(), db.relics.ids().count());
+        for (id, relic) in db.relics.iter() {
+            assert_relative_eq!(
+                partial.single_relic_value(partial.relics.get(&id).unwrap(), Refinement::Intact),
+                db.single_relic_value(relic, Refinement::Intact)
+            );
+        }
+
+        std::fs::write(directory.join("drop_chances.json"), "{\"Intact\": 1}").unwrap();
+        assert!(Database::load_from_file(Some(&prices), Some(&filtered_items)).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn relics_can_list_their_rewards() {
+        let relic: Relic = serde_json::from_str(
+            r#"{
+                "vaulted": false,
+                "rewards": [
+                    {"item": "Forma Blueprint", "rarity": "Rare"},
+                    {"item": "Kuva", "rarity": "Common"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let drops: Vec<_> = relic.drops().collect();
+        assert_eq!(drops.len(), 2);
+        assert_eq!(drops[0].1, Rarity::Common);
+        assert_eq!(drops[1].0, "Forma Blueprint");
+
+        assert!(serde_json::from_str::<Relic>(r#"{"vaulted": true}"#).is_err());
+        assert!(serde_json::from_str::<Relic>(r#"{"vaulted": true, "rare1": ""}"#).is_err());
+    }
+
+    #[test]
+    fn validate_ducat_relic_values() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let blend = Valuation::Blend {
+            platinum_per_ducat: 0.1,
+        };
+
+        for relic in database.relics.meso.values() {
+            let expected: f32 = database
+                .relic_items(relic)
+                .into_iter()
+                .map(|(item, drop)| {
+                    item.ducats as f32 * database.drop_chances.drop_chance(drop, Refinement::Intact)
+                })
+                .sum();
+            assert_relative_eq!(
+                database.shared_relic_value_in(relic, Refinement::Intact, 1, Valuation::Ducats),
+                expected,
+                epsilon = 0.01
+            );
+
+            let blended = database.shared_relic_value_in(relic, Refinement::Radiant, 4, blend);
+            assert!(blended + 0.01 >= database.shared_relic_value(relic, Refinement::Radiant, 4));
+            assert!(
+                blended + 0.01
+                    >= database.shared_relic_value_in(
+                        relic,
+                        Refinement::Radiant,
+                        4,
+                        Valuation::Ducats
+                    ) * 0.1
+            );
+        }
+    }
+
+    #[test]
+    fn validate_mixed_relic_values() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let squads = [
+            vec![Refinement::Intact],
+            vec![Refinement::Radiant, Refinement::Intact],
+            vec![
+                Refinement::Intact,
+                Refinement::Exceptional,
+                Refinement::Flawless,
+            ],
+            vec![
+                Refinement::Radiant,
+                Refinement::Radiant,
+                Refinement::Intact,
+                Refinement::Intact,
+            ],
+        ];
+
+        for relic in database.relics.neo.values() {
+            for squad in squads.iter() {
+                assert_relative_eq!(
+                    database.mixed_relic_value(relic, squad),
+                    database.mixed_relic_value_bruteforce(relic, squad),
+                    epsilon = 0.01
+                )
+            }
+            assert_relative_eq!(
+                database.mixed_relic_value(relic, &[Refinement::Radiant; 4]),
+                database.shared_relic_value(relic, Refinement::Radiant, 4),
+                epsilon = 0.01
+            )
+        }
+    }
+
+    #[test]
+    fn squad_relic_values_match_mixed_values() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let refinements = [
+            Refinement::Radiant,
+            Refinement::Intact,
+            Refinement::Flawless,
+        ];
+        let relics: Vec<_> = database.relics.iter().map(|(_, relic)| relic).collect();
+        for relic in relics.iter() {
+            let squad: Vec<_> = refinements
+                .iter()
+                .map(|&refinement| (*relic, refinement))
+                .collect();
+            assert_relative_eq!(
+                database.squad_relic_value_in(&squad, Valuation::Platinum),
+                database.mixed_relic_value(relic, &refinements),
+                epsilon = 0.01
+            );
+        }
+        let squad = [
+            (relics[0], Refinement::Intact),
+            (relics[1], Refinement::Radiant),
+        ];
+        let value = database.squad_relic_value_in(&squad, Valuation::Platinum);
+        assert!(value >= database.shared_relic_value(relics[0], Refinement::Intact, 1) - 0.01);
+        assert!(value >= database.shared_relic_value(relics[1], Refinement::Radiant, 1) - 0.01);
+    }
+
+    #[test]
+    fn shared_relic_distribution_matches_expectation() {
+        let database = Database::load_from_file(None, None).unwrap();
+
+        for relic in database.relics.lith.values() {
+            for n in 1..=4 {
+                let distribution = database.shared_relic_distribution(
+                    relic,
+                    Refinement::Exceptional,
+                    n,
+                    Valuation::Platinum,
+                );
+                let total: f32 = distribution
+                    .outcomes()
+                    .iter()
+                    .map(|item| item.probability)
+                    .sum();
+                assert_relative_eq!(total, 1.0, epsilon = 0.001);
+                assert_relative_eq!(
+                    distribution.mean(),
+                    database.shared_relic_value(relic, Refinement::Exceptional, n),
+                    epsilon = 0.01
+                );
+            }
+        }
+    }
+
     #[test]
     fn validate_shared_relic_values() {
-        let database = Database::load_from_file(None, None);
+        let database = Database::load_from_file(None, None).unwrap();
 
-        for (name, relic) in database.relics.lith.iter() {
-            println!("{} {:#?}", name, relic);
+        for relic in database.relics.lith.values() {
             assert_relative_eq!(
                 database.shared_relic_value(relic, Refinement::Radiant, 4),
                 database.shared_relic_value_bruteforce(relic, Refinement::Radiant, 4),
                 epsilon = 0.01
             )
         }
-        for (name, relic) in database.relics.meso.iter() {
-            println!("{} {:#?}", name, relic);
+        for relic in database.relics.meso.values() {
             assert_relative_eq!(
                 database.shared_relic_value(relic, Refinement::Radiant, 4),
                 database.shared_relic_value_bruteforce(relic, Refinement::Radiant, 4),
                 epsilon = 0.01
             )
         }
-        for (name, relic) in database.relics.neo.iter() {
-            println!("{} {:#?}", name, relic);
+        for relic in database.relics.neo.values() {
             assert_relative_eq!(
                 database.shared_relic_value(relic, Refinement::Radiant, 4),
                 database.shared_relic_value_bruteforce(relic, Refinement::Radiant, 4),
                 epsilon = 0.01
             )
         }
-        for (name, relic) in database.relics.axi.iter() {
-            println!("{} {:#?}", name, relic);
+        for relic in database.relics.axi.values() {
             assert_relative_eq!(
                 database.shared_relic_value(relic, Refinement::Radiant, 4),
                 database.shared_relic_value_bruteforce(relic, Refinement::Radiant, 4),
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use serde::Serialize;
+
+use crate::{database::Database, inventory::OwnedPart};
+
+#[derive(Clone, Debug)]
+pub struct TradeInOptions {
+    /// Only trade parts that sell for at most this much platinum per ducat.
+    pub platinum_per_ducat: f32,
+    /// Parts that are never traded, and sets of which enough parts are kept to complete one.
+    pub keep: Vec<String>,
+    /// Also keep enough parts to complete one of every set of which any part is owned.
+    pub complete_sets: bool,
+    /// Stop once this many ducats are reached, trading the parts that forgo the least platinum.
+    pub target_ducats: Option<u32>,
+}
+
+/// Copies of a part to trade to Baro Ki'Teer.
+#[derive(Clone, Debug, Serialize)]
+pub struct TradeIn {
+    pub name: String,
+    pub count: u32,
+    pub ducats: u32,
+    pub platinum: f32,
+}
+
+impl TradeIn {
+    pub fn platinum_per_ducat(&self) -> f32 {
+        self.platinum / self.ducats as f32
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TradeInPlan {
+    pub trades: Vec<TradeIn>,
+    pub ducats_gained: u32,
+    pub platinum_forgone: f32,
+}
+
+/// Number of copies of each owned part that may be traded after honouring the keep-list and, with
+/// `complete_sets`, holding back the parts needed to complete every set that is partially owned.
+fn tradeable_parts(
+    database: &Database,
+    owned: &[OwnedPart],
+    keep: &[String],
+    complete_sets: bool,
+) -> Result<HashMap<String, u32>, anyhow::Error> {
+    let mut tradeable: HashMap<String, u32> = HashMap::new();
+    for parts in owned {
+        *tradeable.entry(parts.name.clone()).or_default() += parts.count;
+    }
+    let mut kept_sets = Vec::new();
+    if complete_sets {
+        kept_sets.extend(database.sets().iter().filter(|set| {
+            set.parts
+                .iter()
+                .any(|part| tradeable.get(&part.name).is_some_and(|&count| count > 0))
+        }));
+    }
+    for kept in keep {
+        if let Some(set) = database.find_set(kept) {
+            if !kept_sets.iter().any(|other| other.name == set.name) {
+                kept_sets.push(set);
+            }
+        } else {
+            let item = database
+                .lookup_item(kept)
+                .ok_or_else(|| anyhow!("Unknown item or set to keep: {kept}"))?;
+            tradeable.remove(&item.name);
+        }
+    }
+    for set in kept_sets {
+        for part in set.parts.iter() {
+            if let Some(count) = tradeable.get_mut(&part.name) {
+                *count = count.saturating_sub(part.count);
+            }
+        }
+    }
+    Ok(tradeable)
+}
+
+/// Picks the owned parts to trade for ducats. Without a target every part below the platinum per
+/// ducat threshold is traded, with a target the cheapest selection reaching it is.
+pub fn plan_trade_ins(
+    database: &Database,
+    owned: &[OwnedPart],
+    options: &TradeInOptions,
+) -> Result<TradeInPlan, anyhow::Error> {
+    let mut candidates = Vec::new();
+    for (name, count) in tradeable_parts(database, owned, &options.keep, options.complete_sets)? {
+        let item = database
+            .find_item_exact(&name)
+            .ok_or_else(|| anyhow!("Unknown item: {name}"))?;
+        let ducats = item.ducats as u32;
+        if count == 0 || ducats == 0 || item.platinum > ducats as f32 * options.platinum_per_ducat {
+            continue;
+        }
+        candidates.push(TradeIn {
+            name,
+            count,
+            ducats,
+            platinum: item.platinum,
+        });
+    }
+
+    if let Some(target) = options.target_ducats {
+        candidates = cheapest_reaching(candidates, target);
+    }
+
+    candidates.sort_by(|a, b| {
+        a.platinum_per_ducat()
+            .total_cmp(&b.platinum_per_ducat())
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(TradeInPlan {
+        ducats_gained: candidates
+            .iter()
+            .map(|trade| trade.ducats * trade.count)
+            .sum(),
+        platinum_forgone: candidates.iter().fold(0.0, |total, trade| {
+            total + trade.platinum * trade.count as f32
+        }),
+        trades: candidates,
+    })
+}
+
+/// Chooses copies so that at least `target` ducats are gained for the least platinum, or all of
+/// them if the target cannot be reached.
+fn cheapest_reaching(candidates: Vec<TradeIn>, target: u32) -> Vec<TradeIn> {
+    let copies: Vec<usize> = candidates
+        .iter()
+        .enumerate()
+        .flat_map(|(index, trade)| (0..trade.count).map(move |_| index))
+        .collect();
+    let total: u32 = copies.iter().map(|&index| candidates[index].ducats).sum();
+    if total <= target {
+        return candidates;
+    }
+
+    // cheapest[d] is the least platinum forgone to gain at least d ducats, capped at the target.
+    let target = target as usize;
+    let mut cheapest = vec![f32::INFINITY; target + 1];
+    cheapest[0] = 0.0;
+    let mut taken = Vec::with_capacity(copies.len());
+    for &index in copies.iter() {
+        let trade = &candidates[index];
+        let mut next = cheapest.clone();
+        let mut take = vec![false; target + 1];
+        for ducats in 0..=target {
+            let before = ducats.saturating_sub(trade.ducats as usize);
+            if cheapest[before] + trade.platinum < next[ducats] {
+                next[ducats] = cheapest[before] + trade.platinum;
+                take[ducats] = true;
+            }
+        }
+        cheapest = next;
+        taken.push(take);
+    }
+
+    let mut counts = vec![0; candidates.len()];
+    let mut ducats = target;
+    for (&index, take) in copies.iter().zip(taken.iter()).rev() {
+        if take[ducats] {
+            counts[index] += 1;
+            ducats = ducats.saturating_sub(candidates[index].ducats as usize);
+        }
+    }
+    candidates
+        .into_iter()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(trade, count)| TradeIn { count, ..trade })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use approx::assert_relative_eq;
+
+    use super::*;
+
+    fn owned_parts(database: &Database) -> Vec<OwnedPart> {
+        database
+            .sets()
+            .iter()
+            .flat_map(|set| set.parts.iter())
+            .filter(|part| database.find_item_exact(&part.name).is_some())
+            .map(|part| OwnedPart {
+                name: part.name.clone(),
+                count: 2,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn threshold_and_keep_list_are_respected() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let owned = owned_parts(&database);
+        let set = &database.sets()[0];
+        let options = TradeInOptions {
+            platinum_per_ducat: 1.0,
+            keep: vec![set.name.clone()],
+            complete_sets: false,
+            target_ducats: None,
+        };
+        let plan = plan_trade_ins(&database, &owned, &options).unwrap();
+        for trade in plan.trades.iter() {
+            assert!(trade.platinum_per_ducat() <= 1.0);
+            let kept = set
+                .parts
+                .iter()
+                .find(|part| part.name == trade.name)
+                .map_or(0, |part| part.count);
+            assert!(trade.count <= 2 - kept);
+        }
+        let ducats: u32 = plan.trades.iter().map(|t| t.ducats * t.count).sum();
+        assert_eq!(plan.ducats_gained, ducats);
+    }
+
+    #[test]
+    fn parts_completing_owned_sets_are_kept() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let set = database
+            .sets()
+            .iter()
+            .find(|set| set.parts.len() > 1)
+            .unwrap();
+        // One spare copy of the first part, one of the second and none of the rest
+        let owned = vec![
+            OwnedPart {
+                name: set.parts[0].name.clone(),
+                count: set.parts[0].count + 1,
+            },
+            OwnedPart {
+                name: set.parts[1].name.clone(),
+                count: 1,
+            },
+        ];
+        let mut options = TradeInOptions {
+            platinum_per_ducat: f32::INFINITY,
+            keep: Vec::new(),
+            complete_sets: true,
+            target_ducats: None,
+        };
+        let tradeable = tradeable_parts(&database, &owned, &options.keep, true).unwrap();
+        assert_eq!(tradeable[&set.parts[0].name], 1);
+        assert_eq!(
+            tradeable[&set.parts[1].name],
+            1u32.saturating_sub(set.parts[1].count)
+        );
+
+        let plan = plan_trade_ins(&database, &owned, &options).unwrap();
+        assert!(plan
+            .trades
+            .iter()
+            .all(|trade| trade.count <= 1 && trade.name == set.parts[0].name));
+        // The keep-list doesn't hold back a set twice
+        options.keep.push(set.name.clone());
+        let kept = tradeable_parts(&database, &owned, &options.keep, true).unwrap();
+        assert_eq!(kept, tradeable);
+
+        options.complete_sets = false;
+        options.keep.clear();
+        let plan = plan_trade_ins(&database, &owned, &options).unwrap();
+        let traded: u32 = plan.trades.iter().map(|trade| trade.count).sum();
+        let tradeable: u32 = owned
+            .iter()
+            .filter(|part| database.find_item_exact(&part.name).unwrap().ducats > 0)
+            .map(|part| part.count)
+            .sum();
+        assert_eq!(traded, tradeable);
+    }
+
+    #[test]
+    fn target_matches_bruteforce() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let owned: Vec<OwnedPart> = owned_parts(&database)
+            .into_iter()
+            .take(8)
+            .map(|parts| OwnedPart { count: 1, ..parts })
+            .collect();
+        let items: Vec<_> = owned
+            .iter()
+            .map(|parts| database.find_item_exact(&parts.name).unwrap())
+            .collect();
+        let options = TradeInOptions {
+            platinum_per_ducat: f32::INFINITY,
+            keep: Vec::new(),
+            complete_sets: false,
+            target_ducats: Some(150),
+        };
+        let plan = plan_trade_ins(&database, &owned, &options).unwrap();
+
+        let mut best = f32::INFINITY;
+        for subset in 0..1u32 << items.len() {
+            let picked = items
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| subset & (1 << index) != 0);
+            let ducats: usize = picked.clone().map(|(_, item)| item.ducats).sum();
+            let platinum: f32 = picked.map(|(_, item)| item.platinum).sum();
+            if ducats >= 150 {
+                best = best.min(platinum);
+            }
+        }
+        assert!(plan.ducats_gained >= 150);
+        assert_relative_eq!(plan.platinum_forgone, best, epsilon = 0.01);
+    }
+}
@@ -0,0 +1,393 @@
+use std::{
+    fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+
+use crate::{
+    database::{Database, Valuation},
+    utils::data_directory,
+    wfinfo_data::item_data::{Refinement, RelicId},
+};
+
+/// A calendar day, written as 2024-03-01.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(into = "String")]
+pub struct SnapshotDate {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl SnapshotDate {
+    /// The UTC day of the given point in time.
+    pub fn from_system_time(time: SystemTime) -> SnapshotDate {
+        let seconds = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(err) => -(err.duration().as_secs() as i64),
+        };
+        // Days to civil date, from http://howardhinnant.github.io/date_algorithms.html
+        let days = seconds.div_euclid(86400) + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u32;
+        let year = (year_of_era + era * 400 + i64::from(month <= 2)) as i32;
+        SnapshotDate { year, month, day }
+    }
+
+    pub fn today() -> SnapshotDate {
+        SnapshotDate::from_system_time(SystemTime::now())
+    }
+
+    fn days_in_month(year: i32, month: u32) -> u32 {
+        match month {
+            2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+}
+
+impl fmt::Display for SnapshotDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl From<SnapshotDate> for String {
+    fn from(date: SnapshotDate) -> Self {
+        date.to_string()
+    }
+}
+
+impl FromStr for SnapshotDate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow!("Expected a date like 2024-03-01, got: {s}");
+        let mut parts = s.trim().splitn(3, '-');
+        let mut next = || parts.next().ok_or_else(invalid);
+        let year = next()?.parse().map_err(|_| invalid())?;
+        let month = next()?.parse().map_err(|_| invalid())?;
+        let day = next()?.parse().map_err(|_| invalid())?;
+        if !(1..=12).contains(&month) || !(1..=Self::days_in_month(year, month)).contains(&day) {
+            return Err(invalid());
+        }
+        Ok(SnapshotDate { year, month, day })
+    }
+}
+
+/// A copy of prices.json as it was on the given day.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub date: SnapshotDate,
+    pub path: PathBuf,
+}
+
+/// Dated copies of prices.json, one per day, named like 2024-03-01.json.
+#[derive(Clone, Debug)]
+pub struct PriceHistory {
+    directory: PathBuf,
+}
+
+impl Default for PriceHistory {
+    /// The prices directory in the [`data_directory`].
+    fn default() -> Self {
+        PriceHistory::new(data_directory().join("prices"))
+    }
+}
+
+impl PriceHistory {
+    pub fn new(directory: PathBuf) -> Self {
+        PriceHistory { directory }
+    }
+
+    /// Keeps a copy of the price file, dated by when it was last modified. A later copy from the
+    /// same day replaces the earlier one.
+    pub fn record(&self, prices: &Path) -> Result<Snapshot, anyhow::Error> {
+        let date = SnapshotDate::from_system_time(fs::metadata(prices)?.modified()?);
+        self.record_at(prices, date)
+    }
+
+    /// Keeps a copy of the price file as the snapshot of the given day.
+    pub fn record_at(&self, prices: &Path, date: SnapshotDate) -> Result<Snapshot, anyhow::Error> {
+        let path = self.directory.join(format!("{date}.json"));
+        fs::create_dir_all(&self.directory)?;
+        fs::copy(prices, &path)?;
+        Ok(Snapshot { date, path })
+    }
+
+    /// Lists the stored snapshots from oldest to newest.
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>, anyhow::Error> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut snapshots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let Some(date) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+                .and_then(|date| date.parse().ok())
+            else {
+                continue;
+            };
+            snapshots.push(Snapshot { date, path });
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.date);
+        Ok(snapshots)
+    }
+
+    /// The latest snapshot taken on or before the given day.
+    pub fn snapshot_at(&self, date: SnapshotDate) -> Result<Snapshot, anyhow::Error> {
+        let snapshots = self.snapshots()?;
+        match snapshots
+            .into_iter()
+            .rev()
+            .find(|snapshot| snapshot.date <= date)
+        {
+            Some(snapshot) => Ok(snapshot),
+            None => bail!("No price snapshot on or before {date}"),
+        }
+    }
+
+    /// Loads the database as it was at every snapshot. Relic contents come from the current
+    /// `filtered_items`, only the prices are historical.
+    pub fn databases(
+        &self,
+        filtered_items: &Path,
+    ) -> Result<Vec<(SnapshotDate, Database)>, anyhow::Error> {
+        self.snapshots()?
+            .into_iter()
+            .map(|snapshot| {
+                let database =
+                    Database::load_from_file(Some(&snapshot.path), Some(filtered_items))?;
+                Ok((snapshot.date, database))
+            })
+            .collect()
+    }
+
+    /// Platinum price of an item at every snapshot that lists it.
+    pub fn item_prices(
+        &self,
+        filtered_items: &Path,
+        item_name: &str,
+    ) -> Result<Vec<(SnapshotDate, f32)>, anyhow::Error> {
+        Ok(self
+            .databases(filtered_items)?
+            .into_iter()
+            .filter_map(|(date, database)| {
+                let item = database.find_item_exact(item_name)?;
+                Some((date, item.platinum))
+            })
+            .collect())
+    }
+
+    /// Expected value of a relic at every snapshot that has prices for all of its drops.
+    pub fn relic_values(
+        &self,
+        filtered_items: &Path,
+        relic: &RelicId,
+        refinement: Refinement,
+        number_of_relics: u32,
+        valuation: Valuation,
+    ) -> Result<Vec<(SnapshotDate, f32)>, anyhow::Error> {
+        Ok(self
+            .databases(filtered_items)?
+            .into_iter()
+            .filter_map(|(date, database)| {
+                let value =
+                    relic_value_at(&database, relic, refinement, number_of_relics, valuation)?;
+                Some((date, value))
+            })
+            .collect())
+    }
+}
+
+/// Expected value of a relic in the given database, or `None` if the relic does not exist or a
+/// drop has no price, as happens for items added after an old snapshot was taken.
+pub fn relic_value_at(
+    database: &Database,
+    relic: &RelicId,
+    refinement: Refinement,
+    number_of_relics: u32,
+    valuation: Valuation,
+) -> Option<f32> {
+    let relic = database.relics.get(relic)?;
+    if relic
+        .drops()
+        .any(|(item, _)| database.find_item_exact(item).is_none())
+    {
+        return None;
+    }
+    Some(database.shared_relic_value_in(relic, refinement, number_of_relics, valuation))
+}
+
+/// Change in a relic's expected value between two snapshots.
+#[derive(Clone, Debug, Serialize)]
+pub struct ValueChange {
+    #[serde(flatten)]
+    pub relic: RelicId,
+    pub before: f32,
+    pub after: f32,
+}
+
+impl ValueChange {
+    pub fn change(&self) -> f32 {
+        self.after - self.before
+    }
+}
+
+/// Compares the expected value of every relic priced in both databases, sorted from the biggest
+/// riser to the biggest faller.
+pub fn relic_value_changes(
+    before: &Database,
+    after: &Database,
+    refinement: Refinement,
+    number_of_relics: u32,
+    valuation: Valuation,
+) -> Vec<ValueChange> {
+    let mut changes: Vec<ValueChange> = after
+        .relics
+        .iter()
+        .filter_map(|(relic, _)| {
+            Some(ValueChange {
+                before: relic_value_at(before, &relic, refinement, number_of_relics, valuation)?,
+                after: relic_value_at(after, &relic, refinement, number_of_relics, valuation)?,
+                relic,
+            })
+        })
+        .collect();
+    changes.sort_by(|a, b| b.change().total_cmp(&a.change()));
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn can_convert_dates() {
+        let date =
+            |seconds| SnapshotDate::from_system_time(UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(date(0).to_string(), "1970-01-01");
+        assert_eq!(date(951_782_400).to_string(), "2000-02-29");
+        assert_eq!(date(1_709_337_599).to_string(), "2024-03-01");
+        assert_eq!(
+            "2024-03-01".parse::<SnapshotDate>().unwrap(),
+            date(1_709_251_200)
+        );
+        assert!("2024-13-01".parse::<SnapshotDate>().is_err());
+        assert!("2024-02-29".parse::<SnapshotDate>().is_ok());
+        assert!("2023-02-29".parse::<SnapshotDate>().is_err());
+        assert!("2024-02-31".parse::<SnapshotDate>().is_err());
+        assert!("2024-04-31".parse::<SnapshotDate>().is_err());
+        assert!("1900-02-29".parse::<SnapshotDate>().is_err());
+        assert!("yesterday".parse::<SnapshotDate>().is_err());
+    }
+
+    #[test]
+    fn identical_snapshots_do_not_change() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let changes = relic_value_changes(
+            &database,
+            &database,
+            Refinement::Intact,
+            4,
+            Valuation::Platinum,
+        );
+        assert!(!changes.is_empty());
+        assert!(changes.iter().all(|change| change.change() == 0.0));
+    }
+
+    #[test]
+    fn snapshots_are_compared_in_date_order() {
+        let directory =
+            std::env::temp_dir().join(format!("wfinfo-test-history-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let history = PriceHistory::new(directory.join("prices"));
+        assert!(history.snapshots().unwrap().is_empty());
+        assert!(history.snapshot_at(SnapshotDate::today()).is_err());
+
+        // The rare drop of one relic becomes ten times as valuable a week later
+        let database = Database::load_from_file(None, None).unwrap();
+        let (_, drops) = database.relics.iter().next().unwrap();
+        let rare = &drops.rewards.last().unwrap().item;
+        let dropping_rare: Vec<_> = database
+            .relics
+            .iter()
+            .filter(|(_, relic)| relic.rewards.iter().any(|drop| drop.item == *rare))
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut prices: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string("prices.json").unwrap()).unwrap();
+        for price in prices.as_array_mut().unwrap() {
+            if price["name"] == **rare || price["name"] == format!("{rare} Blueprint") {
+                let platinum: f32 = price["custom_avg"].as_str().unwrap().parse().unwrap();
+                price["custom_avg"] = (platinum * 10.0 + 10.0).to_string().into();
+            }
+        }
+        let later = directory.join("later.json");
+        fs::write(&later, prices.to_string()).unwrap();
+
+        let first: SnapshotDate = "2024-03-01".parse().unwrap();
+        let second: SnapshotDate = "2024-03-08".parse().unwrap();
+        // Recorded out of order on purpose
+        history.record_at(&later, second).unwrap();
+        history.record_at(Path::new("prices.json"), first).unwrap();
+        let today = history.record(&later).unwrap();
+        assert_eq!(today.date, SnapshotDate::today());
+
+        let dates: Vec<_> = history
+            .snapshots()
+            .unwrap()
+            .iter()
+            .map(|snapshot| snapshot.date)
+            .collect();
+        assert_eq!(dates, [first, second, today.date]);
+        assert!(history.snapshot_at("2024-02-29".parse().unwrap()).is_err());
+        let before = history.snapshot_at("2024-03-07".parse().unwrap()).unwrap();
+        let after = history.snapshot_at(second).unwrap();
+        assert_eq!((before.date, after.date), (first, second));
+
+        let filtered_items = Path::new("filtered_items.json");
+        let before = Database::load_from_file(Some(&before.path), Some(filtered_items)).unwrap();
+        let after = Database::load_from_file(Some(&after.path), Some(filtered_items)).unwrap();
+        let changes =
+            relic_value_changes(&before, &after, Refinement::Intact, 1, Valuation::Platinum);
+        let rising: Vec<_> = changes
+            .iter()
+            .filter(|change| change.change() > 0.0)
+            .map(|change| change.relic.clone())
+            .collect();
+        assert_eq!(rising.len(), dropping_rare.len());
+        assert!(rising.iter().all(|relic| dropping_rare.contains(relic)));
+        assert!(changes
+            .windows(2)
+            .all(|pair| pair[0].change() >= pair[1].change()));
+        assert!(changes.iter().all(|change| change.change() >= 0.0));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,519 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{Database, Valuation},
+    utils::data_directory,
+    wfinfo_data::item_data::{Refinement, RelicId},
+};
+
+/// Everything the player owns that the tools keep track of, stored as JSON between runs.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Inventory {
+    #[serde(default)]
+    pub relics: Vec<OwnedRelic>,
+    #[serde(default)]
+    pub parts: Vec<OwnedPart>,
+}
+
+/// A stack of identical relics the player owns, e.g. three Intact Lith A1.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OwnedRelic {
+    #[serde(flatten)]
+    pub relic: RelicId,
+    pub refinement: Refinement,
+    pub count: u32,
+}
+
+impl OwnedRelic {
+    fn same_stack(&self, other: &OwnedRelic) -> bool {
+        self.relic == other.relic && self.refinement == other.refinement
+    }
+}
+
+impl fmt::Display for OwnedRelic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{:?}={}", self.relic, self.refinement, self.count)
+    }
+}
+
+/// Parses relic stacks written as "Lith A1=3" or "Lith A1:Radiant=2". The refinement defaults to
+/// Intact and the count to 1.
+impl FromStr for OwnedRelic {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (relic, count) = match s.rsplit_once('=') {
+            Some((relic, count)) => (relic, count.trim().parse()?),
+            None => (s, 1),
+        };
+        let (relic, refinement) = match relic.split_once(':') {
+            Some((relic, refinement)) => (relic, refinement.trim().parse()?),
+            None => (relic, Refinement::Intact),
+        };
+        Ok(OwnedRelic {
+            relic: relic.parse()?,
+            refinement,
+            count,
+        })
+    }
+}
+
+/// Copies of a prime part the player owns, e.g. two Titania Prime Systems.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OwnedPart {
+    pub name: String,
+    pub count: u32,
+}
+
+impl fmt::Display for OwnedPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.count)
+    }
+}
+
+/// Parses parts written as "Titania Prime Systems=2", the count defaults to 1.
+impl FromStr for OwnedPart {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, count) = match s.rsplit_once('=') {
+            Some((name, count)) => (name, count.trim().parse()?),
+            None => (s, 1),
+        };
+        if name.trim().is_empty() {
+            bail!("Expected a part like \"Titania Prime Systems=2\", got: {s}");
+        }
+        Ok(OwnedPart {
+            name: name.trim().to_string(),
+            count,
+        })
+    }
+}
+
+impl Inventory {
+    /// inventory.json in the [`data_directory`].
+    pub fn default_path() -> PathBuf {
+        data_directory().join("inventory.json")
+    }
+
+    /// Reads the inventory, treating a missing file as an empty inventory.
+    pub fn load(path: &Path) -> Result<Inventory, anyhow::Error> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Inventory::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn add_relics(&mut self, stack: &OwnedRelic) {
+        match self.relics.iter_mut().find(|owned| owned.same_stack(stack)) {
+            Some(owned) => owned.count += stack.count,
+            None => self.relics.push(stack.clone()),
+        }
+    }
+
+    pub fn remove_relics(&mut self, stack: &OwnedRelic) -> Result<(), anyhow::Error> {
+        let Some(index) = self.relics.iter().position(|owned| owned.same_stack(stack)) else {
+            bail!("No {} {:?} in the inventory", stack.relic, stack.refinement);
+        };
+        let owned = &mut self.relics[index];
+        if owned.count < stack.count {
+            bail!("Cannot remove {stack}, only {} owned", owned.count);
+        }
+        owned.count -= stack.count;
+        if owned.count == 0 {
+            self.relics.remove(index);
+        }
+        Ok(())
+    }
+
+    /// Replaces the count of a stack, removing it when the count is zero.
+    pub fn set_relics(&mut self, stack: &OwnedRelic) {
+        self.relics.retain(|owned| !owned.same_stack(stack));
+        if stack.count > 0 {
+            self.relics.push(stack.clone());
+        }
+    }
+
+    pub fn add_parts(&mut self, parts: &OwnedPart) {
+        match self.parts.iter_mut().find(|owned| owned.name == parts.name) {
+            Some(owned) => owned.count += parts.count,
+            None => self.parts.push(parts.clone()),
+        }
+    }
+
+    pub fn remove_parts(&mut self, parts: &OwnedPart) -> Result<(), anyhow::Error> {
+        let Some(index) = self.parts.iter().position(|owned| owned.name == parts.name) else {
+            bail!("No {} in the inventory", parts.name);
+        };
+        let owned = &mut self.parts[index];
+        if owned.count < parts.count {
+            bail!("Cannot remove {parts}, only {} owned", owned.count);
+        }
+        owned.count -= parts.count;
+        if owned.count == 0 {
+            self.parts.remove(index);
+        }
+        Ok(())
+    }
+
+    /// Replaces the owned count of a part, removing it when the count is zero.
+    pub fn set_parts(&mut self, parts: &OwnedPart) {
+        self.parts.retain(|owned| owned.name != parts.name);
+        if parts.count > 0 {
+            self.parts.push(parts.clone());
+        }
+    }
+
+    /// Compares scanned part counts to the owned ones, sorted by name. Parts missing from the
+    /// scan are left alone, as a scan may only cover some of the inventory.
+    pub fn part_changes(&self, scanned: &[OwnedPart]) -> Vec<PartChange> {
+        let mut changes: Vec<_> = scanned
+            .iter()
+            .map(|parts| PartChange {
+                name: parts.name.clone(),
+                before: self
+                    .parts
+                    .iter()
+                    .find(|owned| owned.name == parts.name)
+                    .map_or(0, |owned| owned.count),
+                after: parts.count,
+            })
+            .filter(|change| change.before != change.after)
+            .collect();
+        changes.sort_by(|a, b| a.name.cmp(&b.name));
+        changes
+    }
+
+    pub fn apply_part_changes(&mut self, changes: &[PartChange]) {
+        for change in changes {
+            self.set_parts(&OwnedPart {
+                name: change.name.clone(),
+                count: change.after,
+            });
+        }
+    }
+}
+
+/// Owned count of a part before and after applying a scan.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PartChange {
+    pub name: String,
+    pub before: u32,
+    pub after: u32,
+}
+
+/// Expected value of cracking one relic of an owned stack.
+#[derive(Clone, Debug, Serialize)]
+pub struct CrackAdvice {
+    pub relic: OwnedRelic,
+    pub vaulted: bool,
+    pub value: f32,
+    /// Expected platinum from cracking, used to compare against the sale price.
+    pub platinum: f32,
+    /// Market price of the intact relic. The price data has none for refined relics, which sell
+    /// for at least as much, so for refined stacks this is a lower bound.
+    pub sell_price: Option<f32>,
+    /// The relic is vaulted and sells for more platinum than cracking it is expected to give,
+    /// comparing refined stacks against the intact price.
+    pub sell_instead: bool,
+}
+
+/// Ranks the owned relic stacks by the expected value of cracking one of them, best first, with
+/// `number_of_relics` copies of the relic in the squad.
+pub fn plan_cracks(
+    database: &Database,
+    owned: &[OwnedRelic],
+    number_of_relics: u32,
+    valuation: Valuation,
+) -> Result<Vec<CrackAdvice>, anyhow::Error> {
+    let mut advice = owned
+        .iter()
+        .map(|stack| {
+            let relic = database
+                .relics
+                .get(&stack.relic)
+                .ok_or_else(|| anyhow!("Unknown relic: {}", stack.relic))?;
+            let platinum = database.shared_relic_value(relic, stack.refinement, number_of_relics);
+            // Only intact relics are priced, see CrackAdvice::sell_price
+            let sell_price = database.relic_price(&stack.relic);
+            Ok(CrackAdvice {
+                relic: stack.clone(),
+                vaulted: relic.vaulted,
+                value: database.shared_relic_value_in(
+                    relic,
+                    stack.refinement,
+                    number_of_relics,
+                    valuation,
+                ),
+                platinum,
+                sell_price,
+                sell_instead: relic.vaulted && sell_price.is_some_and(|price| price > platinum),
+            })
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    advice.sort_by(|a, b| b.value.total_cmp(&a.value));
+    Ok(advice)
+}
+
+/// Complete sets worth more sold whole than as parts.
+#[derive(Clone, Debug, Serialize)]
+pub struct SetSale {
+    pub set: String,
+    pub count: u32,
+    pub set_platinum: f32,
+    pub parts_platinum: f32,
+}
+
+/// Parts that are sold on their own.
+#[derive(Clone, Debug, Serialize)]
+pub struct PartSale {
+    pub name: String,
+    pub count: u32,
+    pub platinum: f32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SalePlan {
+    pub sets: Vec<SetSale>,
+    pub parts: Vec<PartSale>,
+    pub total_platinum: f32,
+}
+
+/// Splits the owned parts into complete sets that sell for more than their parts, and the parts
+/// left to sell individually.
+pub fn plan_sales(database: &Database, owned: &[OwnedPart]) -> Result<SalePlan, anyhow::Error> {
+    let mut remaining: HashMap<&str, u32> = HashMap::new();
+    for parts in owned {
+        if database.find_item_exact(&parts.name).is_none() {
+            bail!("Unknown item: {}", parts.name);
+        }
+        *remaining.entry(&parts.name).or_default() += parts.count;
+    }
+
+    let mut sets = Vec::new();
+    for set in database.sets() {
+        let Some(parts_platinum) = database.parts_platinum(set) else {
+            continue;
+        };
+        if set.platinum <= parts_platinum {
+            continue;
+        }
+        let complete = set
+            .parts
+            .iter()
+            .map(|part| remaining.get(part.name.as_str()).copied().unwrap_or(0) / part.count)
+            .min()
+            .unwrap_or(0);
+        if complete == 0 {
+            continue;
+        }
+        for part in set.parts.iter() {
+            *remaining.get_mut(part.name.as_str()).unwrap() -= part.count * complete;
+        }
+        sets.push(SetSale {
+            set: set.name.clone(),
+            count: complete,
+            set_platinum: set.platinum,
+            parts_platinum,
+        });
+    }
+
+    let mut parts: Vec<PartSale> = remaining
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(name, count)| PartSale {
+            name: name.to_string(),
+            count,
+            platinum: database.find_item_exact(name).unwrap().platinum,
+        })
+        .collect();
+
+    sets.sort_by(|a, b| {
+        (b.set_platinum * b.count as f32).total_cmp(&(a.set_platinum * a.count as f32))
+    });
+    parts.sort_by(|a, b| {
+        (b.platinum * b.count as f32)
+            .total_cmp(&(a.platinum * a.count as f32))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    let total_platinum = sets
+        .iter()
+        .map(|sale| sale.set_platinum * sale.count as f32)
+        .chain(parts.iter().map(|sale| sale.platinum * sale.count as f32))
+        .fold(0.0, |total, platinum| total + platinum);
+    Ok(SalePlan {
+        sets,
+        parts,
+        total_platinum,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wfinfo_data::item_data::RelicTier;
+
+    #[test]
+    fn can_parse_owned_relics() {
+        let stack: OwnedRelic = "axi a1:radiant=2".parse().unwrap();
+        assert_eq!(stack.relic, RelicId::new(RelicTier::Axi, "A1"));
+        assert_eq!(stack.refinement, Refinement::Radiant);
+        assert_eq!(stack.count, 2);
+        assert_eq!(stack.to_string().parse::<OwnedRelic>().unwrap(), stack);
+
+        assert!("Void A1".parse::<OwnedRelic>().is_err());
+    }
+
+    #[test]
+    fn inventory_merges_stacks() {
+        let mut inventory = Inventory::default();
+        inventory.add_relics(&"Lith A1=2".parse().unwrap());
+        inventory.add_relics(&"Lith A1=1".parse().unwrap());
+        inventory.add_relics(&"Lith A1:Radiant".parse().unwrap());
+        assert_eq!(inventory.relics.len(), 2);
+        assert_eq!(inventory.relics[0].count, 3);
+
+        inventory
+            .remove_relics(&"Lith A1=3".parse().unwrap())
+            .unwrap();
+        assert!(inventory
+            .remove_relics(&"Lith A1:Radiant=2".parse().unwrap())
+            .is_err());
+        inventory.set_relics(&"Lith A1:Radiant=0".parse().unwrap());
+        assert!(inventory.relics.is_empty());
+    }
+
+    #[test]
+    fn scanned_parts_are_diffed() {
+        let mut inventory = Inventory::default();
+        inventory.add_parts(&"Titania Prime Systems=2".parse().unwrap());
+        inventory.add_parts(&"Octavia Prime Blueprint=1".parse().unwrap());
+        let scanned: Vec<OwnedPart> = [
+            "Titania Prime Systems=3",
+            "Octavia Prime Blueprint=1",
+            "Burston Prime Stock=2",
+        ]
+        .into_iter()
+        .map(|parts| parts.parse().unwrap())
+        .collect();
+
+        let changes = inventory.part_changes(&scanned);
+        assert_eq!(
+            changes,
+            vec![
+                PartChange {
+                    name: "Burston Prime Stock".to_string(),
+                    before: 0,
+                    after: 2
+                },
+                PartChange {
+                    name: "Titania Prime Systems".to_string(),
+                    before: 2,
+                    after: 3
+                },
+            ]
+        );
+        inventory.apply_part_changes(&changes);
+        assert!(inventory.part_changes(&scanned).is_empty());
+        assert_eq!(inventory.parts.len(), 3);
+    }
+
+    #[test]
+    fn cracks_are_ranked_by_value() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let owned: Vec<OwnedRelic> = database
+            .relics
+            .tier(RelicTier::Lith)
+            .keys()
+            .map(|name| OwnedRelic {
+                relic: RelicId::new(RelicTier::Lith, name),
+                refinement: Refinement::Radiant,
+                count: 1,
+            })
+            .collect();
+        let advice = plan_cracks(&database, &owned, 4, Valuation::Platinum).unwrap();
+        assert_eq!(advice.len(), owned.len());
+        assert!(advice.windows(2).all(|pair| pair[0].value >= pair[1].value));
+        assert!(advice
+            .iter()
+            .all(|crack| !crack.sell_instead || crack.vaulted));
+    }
+
+    #[test]
+    fn refined_stacks_are_compared_against_the_intact_price() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let owned: Vec<OwnedRelic> = database
+            .relics
+            .iter()
+            .filter(|(id, _)| database.relic_price(id).is_some())
+            .flat_map(|(id, _)| {
+                [Refinement::Intact, Refinement::Radiant].map(|refinement| OwnedRelic {
+                    relic: id.clone(),
+                    refinement,
+                    count: 1,
+                })
+            })
+            .collect();
+        assert!(!owned.is_empty());
+
+        for crack in plan_cracks(&database, &owned, 1, Valuation::Platinum).unwrap() {
+            let intact_price = database.relic_price(&crack.relic.relic).unwrap();
+            assert_eq!(crack.sell_price, Some(intact_price));
+            assert_eq!(
+                crack.sell_instead,
+                crack.vaulted && intact_price > crack.platinum
+            );
+        }
+    }
+
+    #[test]
+    fn sets_are_sold_whole_when_worth_more() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let set = database
+            .sets()
+            .iter()
+            .find(|set| database.parts_platinum(set).is_some())
+            .expect("No priced sets in database");
+        let mut owned: Vec<OwnedPart> = set
+            .parts
+            .iter()
+            .map(|part| OwnedPart {
+                name: part.name.clone(),
+                count: part.count,
+            })
+            .collect();
+        owned[0].count += 1;
+
+        let plan = plan_sales(&database, &owned).unwrap();
+        let sold_whole = set.platinum > database.parts_platinum(set).unwrap();
+        assert_eq!(plan.sets.len(), usize::from(sold_whole));
+        let sold_parts: u32 = plan.parts.iter().map(|sale| sale.count).sum();
+        let owned_parts: u32 = owned.iter().map(|parts| parts.count).sum();
+        if sold_whole {
+            assert_eq!(sold_parts, 1);
+        } else {
+            assert_eq!(sold_parts, owned_parts);
+        }
+
+        assert!(plan_sales(&database, &["Not A Part".parse().unwrap()]).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use image::DynamicImage;
+use log::debug;
+use serde::Serialize;
+
+use crate::{
+    database::Database,
+    inventory::OwnedPart,
+    ocr::{detect_theme, filter_text, image_to_string, normalize_string, OCR},
+    theme::Theme,
+};
+
+// Prime part grid of the inventory and foundry screens at 1920x1080. Like the tile regions below
+// these are estimates that have not been checked against a capture of the game, which is why
+// `relics inventory scan` needs --experimental
+const PIXEL_GRID_LEFT: f32 = 96.0;
+const PIXEL_GRID_TOP: f32 = 224.0;
+const PIXEL_GRID_WIDTH: f32 = 1280.0;
+const PIXEL_GRID_HEIGHT: f32 = 768.0;
+const PIXEL_MIN_TILE_SIZE: f32 = 64.0;
+
+/// Pixels at most this bright belong to the gutters between tiles.
+const GUTTER_LUMA: u8 = 40;
+/// Share of gutter pixels a row or column needs to separate two tiles.
+const GUTTER_FILL: f32 = 0.9;
+
+// Parts of a tile as fractions of its size: left, top, width and height
+const COUNT_REGION: [f32; 4] = [0.0, 0.0, 0.4, 0.25];
+const NAME_REGION: [f32; 4] = [0.0, 0.62, 1.0, 0.38];
+
+/// A tile of the inventory grid, in screenshot pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    fn region(&self, [left, top, width, height]: [f32; 4]) -> (u32, u32, u32, u32) {
+        (
+            self.x + (left * self.width as f32) as u32,
+            self.y + (top * self.height as f32) as u32,
+            (width * self.width as f32) as u32,
+            (height * self.height as f32) as u32,
+        )
+    }
+}
+
+fn screen_scaling(image: &DynamicImage) -> f32 {
+    if image.width() * 9 > image.height() * 16 {
+        image.height() as f32 / 1080.0
+    } else {
+        image.width() as f32 / 1920.0
+    }
+}
+
+/// Start and length of every run of non-gutter lines at least `min_size` long.
+fn spans(gutters: &[bool], min_size: u32) -> Vec<(u32, u32)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (index, &gutter) in gutters.iter().chain([&true]).enumerate() {
+        match (start, gutter) {
+            (None, false) => start = Some(index),
+            (Some(first), true) => {
+                if index - first >= min_size as usize {
+                    spans.push((first as u32, (index - first) as u32));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+/// Finds the tiles of the prime part grid from the dark gutters running between them. Empty
+/// slots are included, they are skipped when reading the tiles.
+pub fn detect_grid(image: &DynamicImage) -> Vec<Tile> {
+    let screen_scaling = screen_scaling(image);
+    let left = (PIXEL_GRID_LEFT * screen_scaling) as u32;
+    let top = (PIXEL_GRID_TOP * screen_scaling) as u32;
+    let width =
+        ((PIXEL_GRID_WIDTH * screen_scaling) as u32).min(image.width().saturating_sub(left));
+    let height =
+        ((PIXEL_GRID_HEIGHT * screen_scaling) as u32).min(image.height().saturating_sub(top));
+    let grid = image.crop_imm(left, top, width, height).to_luma8();
+
+    let mut column_gutter = vec![0; width as usize];
+    let mut row_gutter = vec![0; height as usize];
+    for (x, y, pixel) in grid.enumerate_pixels() {
+        if pixel.0[0] <= GUTTER_LUMA {
+            column_gutter[x as usize] += 1;
+            row_gutter[y as usize] += 1;
+        }
+    }
+    let is_gutter = |count: u32, length: u32| count as f32 >= length as f32 * GUTTER_FILL;
+    let columns: Vec<_> = column_gutter
+        .into_iter()
+        .map(|count| is_gutter(count, height))
+        .collect();
+    let rows: Vec<_> = row_gutter
+        .into_iter()
+        .map(|count| is_gutter(count, width))
+        .collect();
+
+    let min_size = (PIXEL_MIN_TILE_SIZE * screen_scaling) as u32;
+    let columns = spans(&columns, min_size);
+    let rows = spans(&rows, min_size);
+    debug!(
+        "Inventory grid: {} columns, {} rows",
+        columns.len(),
+        rows.len()
+    );
+
+    rows.iter()
+        .flat_map(|&(y, height)| {
+            columns.iter().map(move |&(x, width)| Tile {
+                x: left + x,
+                y: top + y,
+                width,
+                height,
+            })
+        })
+        .collect()
+}
+
+/// Reads the name and stack count text of a tile, or `None` for an empty slot.
+pub fn read_tile(image: &DynamicImage, tile: &Tile, theme: &Theme) -> Option<(String, String)> {
+    let (x, y, width, height) = tile.region(NAME_REGION);
+    let (name, text_pixels) = filter_text(&image.crop_imm(x, y, width, height), theme);
+    // A name covers at least a few percent of its part of the tile
+    if text_pixels < width * height / 50 {
+        return None;
+    }
+    let name = image_to_string(&mut OCR.lock().unwrap(), &name);
+
+    let (x, y, width, height) = tile.region(COUNT_REGION);
+    let (count, text_pixels) = filter_text(&image.crop_imm(x, y, width, height), theme);
+    let count = if text_pixels > 0 {
+        image_to_string(&mut OCR.lock().unwrap(), &count)
+    } else {
+        String::new()
+    };
+    debug!("Tile {:?}: {:?} x {:?}", tile, name, count);
+    Some((name, count))
+}
+
+/// Reads a stack count like "3" or "x3". Tiles of a single part show no count.
+pub fn parse_count(text: &str) -> u32 {
+    let digits: String = text.chars().filter(|c| c.is_ascii_digit()).collect();
+    digits.parse().unwrap_or(1)
+}
+
+/// Prime parts read from inventory screenshots.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct InventoryScan {
+    pub parts: Vec<OwnedPart>,
+    /// Tile names that did not match an item in the database.
+    pub unknown: Vec<String>,
+}
+
+impl InventoryScan {
+    /// Adds the text read from one tile. Every part has a single tile, so a part seen on several
+    /// overlapping screenshots is only counted once.
+    pub fn add(&mut self, database: &Database, name: &str, count: &str) {
+        let Some(item) = database.find_item(&normalize_string(name), None) else {
+            let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !name.is_empty() && !self.unknown.contains(&name) {
+                self.unknown.push(name);
+            }
+            return;
+        };
+        let count = parse_count(count);
+        match self.parts.iter_mut().find(|parts| parts.name == item.name) {
+            Some(parts) => parts.count = parts.count.max(count),
+            None => self.parts.push(OwnedPart {
+                name: item.name.clone(),
+                count,
+            }),
+        }
+    }
+}
+
+/// Reads the prime parts from screenshots of the inventory or foundry grid. Without a theme it is
+/// detected for every screenshot.
+pub fn scan_inventory(
+    images: &[DynamicImage],
+    theme: Option<Theme>,
+    database: &Database,
+) -> InventoryScan {
+    let mut scan = InventoryScan::default();
+    for image in images {
+        let theme = theme
+            .clone()
+            .unwrap_or_else(|| detect_theme(image).into_best());
+        for tile in detect_grid(image) {
+            if let Some((name, count)) = read_tile(image, &tile, &theme) {
+                scan.add(database, &name, &count);
+            }
+        }
+    }
+    scan
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    fn grid_image(width: u32, height: u32, tile: u32, gutter: u32) -> (DynamicImage, Vec<Tile>) {
+        let scaling = height as f32 / 1080.0;
+        let left = (PIXEL_GRID_LEFT * scaling) as u32 + gutter;
+        let top = (PIXEL_GRID_TOP * scaling) as u32 + gutter;
+        let mut image = RgbImage::from_pixel(width, height, Rgb([12, 14, 20]));
+        let mut tiles = Vec::new();
+        for row in 0..2 {
+            for column in 0..3 {
+                let tile = Tile {
+                    x: left + column * (tile + gutter),
+                    y: top + row * (tile + gutter),
+                    width: tile,
+                    height: tile,
+                };
+                for y in tile.y..tile.y + tile.height {
+                    for x in tile.x..tile.x + tile.width {
+                        // Icons have some dark pixels too
+                        let dark = (x + y) % 7 == 0;
+                        image.put_pixel(x, y, if dark { Rgb([0; 3]) } else { Rgb([70, 80, 90]) });
+                    }
+                }
+                tiles.push(tile);
+            }
+        }
+        (DynamicImage::ImageRgb8(image), tiles)
+    }
+
+    #[test]
+    fn detects_grid_tiles() {
+        let (image, tiles) = grid_image(1920, 1080, 150, 20);
+        assert_eq!(detect_grid(&image), tiles);
+
+        let (image, tiles) = grid_image(2560, 1440, 200, 26);
+        assert_eq!(detect_grid(&image), tiles);
+
+        let empty = DynamicImage::ImageRgb8(RgbImage::new(1920, 1080));
+        assert!(detect_grid(&empty).is_empty());
+    }
+
+    #[test]
+    fn scans_merge_overlapping_screenshots() {
+        assert_eq!(parse_count("x3"), 3);
+        assert_eq!(parse_count("12\n"), 12);
+        assert_eq!(parse_count(""), 1);
+
+        let database = Database::load_from_file(None, None).unwrap();
+        let mut scan = InventoryScan::default();
+        scan.add(&database, "Titania Prime Systems\nBlueprint", "x2");
+        scan.add(&database, "Titania Prime Systems Blueprint", "2");
+        scan.add(&database, "Burston Prime Stock", "");
+        scan.add(&database, "Mystery Tile", "4");
+        assert_eq!(
+            scan.parts,
+            vec![
+                OwnedPart {
+                    name: "Titania Prime Systems".to_string(),
+                    count: 2
+                },
+                OwnedPart {
+                    name: "Burston Prime Stock".to_string(),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(scan.unknown, vec!["Mystery Tile".to_string()]);
+    }
+}
@@ -1,7 +1,15 @@
 pub mod database;
+pub mod ducats;
+pub mod history;
+pub mod inventory;
+pub mod inventory_scan;
 pub mod ocr;
+pub mod snap;
 pub mod statistics;
 pub mod testing;
 pub mod theme;
+pub mod theme_fit;
+pub mod trade_chat;
+pub mod traces;
 pub mod utils;
 pub mod wfinfo_data;
@@ -8,48 +8,105 @@ use tesseract::Tesseract;
 use image::{DynamicImage, GenericImageView, Pixel, Rgb};
 use log::debug;
 
-use crate::theme::Theme;
+use crate::database::{Database, Reward};
+use crate::theme::{ColorHistogram, Theme, ThemeRanking};
+use crate::wfinfo_data::item_data::{Refinement, RelicId, Relics};
 
 const PIXEL_REWARD_WIDTH: f32 = 968.0;
 const PIXEL_REWARD_HEIGHT: f32 = 235.0;
 const PIXEL_REWARD_YDISPLAY: f32 = 316.0;
 const PIXEL_REWARD_LINE_HEIGHT: f32 = 48.0;
 
-pub fn detect_theme(image: &DynamicImage) -> Theme {
-    // Get the size of primary monitor
-    let monitors = xcap::Monitor::all().unwrap();
+// Relic names on the void projection screen, one column per squad member, at 1920x1080. Not yet
+// checked against a capture of the screen
+const PIXEL_SQUAD_RELIC_WIDTH: f32 = 1240.0;
+const PIXEL_SQUAD_RELIC_TOP: f32 = 622.0;
+const PIXEL_SQUAD_RELIC_HEIGHT: f32 = 2.0 * PIXEL_REWARD_LINE_HEIGHT;
+const SQUAD_SIZE: u32 = 4;
+
+/// Below this share of read reward names matching an item the rewards are read again with the
+/// runner-up theme.
+const MIN_MATCH_CONFIDENCE: f32 = 0.75;
+
+/// Where theme detection samples the screenshot: from the top of the reward area down, a centred
+/// strip of pixels that widens towards the bottom.
+struct ThemeSample {
+    line_height: f32,
+    most_width: f32,
+    min_width: f32,
+    height: u32,
+}
 
-    let primary_width = monitors[0].width() as f32;
-    let primary_height = monitors[0].height() as f32;
+impl ThemeSample {
+    fn of(image: &DynamicImage) -> ThemeSample {
+        // Get the size of primary monitor
+        let monitors = xcap::Monitor::all().unwrap();
 
-    let aspect_ratio = Fraction::from(primary_width / primary_height);
-    let primary_width_aspect = aspect_ratio.numer().unwrap().to_owned() as u32;
-    let primary_height_aspect = aspect_ratio.denom().unwrap().to_owned() as u32;
+        let primary_width = monitors[0].width() as f32;
+        let primary_height = monitors[0].height() as f32;
 
-    // Calculate screen_scaling according to current monitor dimensions
-    let screen_scaling: f32 =
-        if image.width() * primary_width_aspect > image.height() * primary_height_aspect {
-            image.height() as f32 / primary_height
-        } else {
-            image.width() as f32 / primary_width
-        };
+        let aspect_ratio = Fraction::from(primary_width / primary_height);
+        let primary_width_aspect = aspect_ratio.numer().unwrap().to_owned() as u32;
+        let primary_height_aspect = aspect_ratio.denom().unwrap().to_owned() as u32;
 
-    let line_height = PIXEL_REWARD_LINE_HEIGHT / 2.0 * screen_scaling;
-    let most_width = PIXEL_REWARD_WIDTH * screen_scaling;
+        // Calculate screen_scaling according to current monitor dimensions
+        let screen_scaling: f32 =
+            if image.width() * primary_width_aspect > image.height() * primary_height_aspect {
+                image.height() as f32 / primary_height
+            } else {
+                image.width() as f32 / primary_width
+            };
 
-    let min_width = most_width / 4.0;
+        let line_height = PIXEL_REWARD_LINE_HEIGHT / 2.0 * screen_scaling;
+        let most_width = PIXEL_REWARD_WIDTH * screen_scaling;
+
+        ThemeSample {
+            line_height,
+            most_width,
+            min_width: most_width / 4.0,
+            height: image.height(),
+        }
+    }
+
+    fn rows(&self) -> std::ops::Range<u32> {
+        self.line_height as u32..self.height
+    }
 
-    let weights = (line_height as u32..image.height())
+    /// The sampled pixels of a row.
+    fn row<'a>(&self, image: &'a DynamicImage, y: u32) -> impl Iterator<Item = Rgb<u8>> + 'a {
+        let perc = (y as f32 - self.line_height) / (self.height as f32 - self.line_height);
+        let total_width = self.min_width * perc + self.min_width;
+        let left = (self.most_width - total_width) as u32 / 2;
+        (0..total_width as u32).map(move |x| image.get_pixel(x + left, y).to_rgb())
+    }
+}
+
+/// Ranks the themes by how much of the reward area is close to their primary colour. The
+/// sampled pixels are counted in bins of similar colours first, as the reward screen has far
+/// fewer distinct colours than pixels.
+pub fn detect_theme(image: &DynamicImage) -> ThemeRanking {
+    let sample = ThemeSample::of(image);
+    let histogram: ColorHistogram = sample
+        .rows()
+        .flat_map(|y| sample.row(image, y))
+        .collect();
+    debug!("{} colour bins sampled", histogram.len());
+
+    let ranking = ThemeRanking::from_histogram(&histogram);
+    debug!("{:#?}", ranking);
+    ranking
+}
+
+/// [`detect_theme`] comparing every sampled pixel with every theme, kept to benchmark and check
+/// it against.
+pub fn detect_theme_per_pixel(image: &DynamicImage) -> ThemeRanking {
+    let sample = ThemeSample::of(image);
+    let weights = sample
+        .rows()
         .into_par_iter()
-        .fold(HashMap::new, |mut weights: HashMap<Theme, f32>, y| {
-            let perc = (y as f32 - line_height) / (image.height() as f32 - line_height);
-            let total_width = min_width * perc + min_width;
-            for x in 0..total_width as u32 {
-                let closest = Theme::closest_from_color(
-                    image
-                        .get_pixel(x + (most_width - total_width) as u32 / 2, y)
-                        .to_rgb(),
-                );
+        .fold(HashMap::new, |mut weights: HashMap<&Theme, f32>, y| {
+            for color in sample.row(image, y) {
+                let closest = Theme::closest_from_color(color);
 
                 *weights.entry(closest.0).or_insert(0.0) += 1.0 / (1.0 + closest.1).powi(4)
             }
@@ -62,14 +119,7 @@ pub fn detect_theme(image: &DynamicImage) -> Theme {
             a
         });
 
-    debug!("{:#?}", weights);
-
-    weights
-        .iter()
-        .max_by(|a, b| a.1.total_cmp(b.1))
-        .unwrap()
-        .0
-        .to_owned()
+    ThemeRanking::from_weights(weights)
 }
 
 pub fn extract_parts(image: &DynamicImage, theme: Theme) -> Vec<DynamicImage> {
@@ -341,6 +391,21 @@ pub fn filter_and_separate_parts_from_part_box(
     images
 }
 
+/// Keeps only the text pixels, as black on white, and counts them.
+pub fn filter_text(image: &DynamicImage, theme: &Theme) -> (DynamicImage, u32) {
+    let mut filtered = image.to_rgb8();
+    let mut text_pixels = 0;
+    for pixel in filtered.pixels_mut() {
+        *pixel = if theme.threshold_filter(pixel.to_rgb()) {
+            text_pixels += 1;
+            Rgb([0; 3])
+        } else {
+            Rgb([255; 3])
+        };
+    }
+    (DynamicImage::ImageRgb8(filtered), text_pixels)
+}
+
 pub fn normalize_string(string: &str) -> String {
     string.replace(|c: char| !c.is_ascii_alphabetic(), "")
 }
@@ -371,7 +436,7 @@ lazy_static! {
 }
 
 pub fn reward_image_to_reward_names(image: DynamicImage, theme: Option<Theme>) -> Vec<String> {
-    let theme = theme.unwrap_or_else(|| detect_theme(&image));
+    let theme = theme.unwrap_or_else(|| detect_theme(&image).into_best());
     let parts = extract_parts(&image, theme);
     debug!("Extracted part images");
 
@@ -380,3 +445,177 @@ pub fn reward_image_to_reward_names(image: DynamicImage, theme: Option<Theme>) -
         .map(|image| image_to_string(&mut OCR.lock().unwrap(), image))
         .collect()
 }
+
+/// Rewards read from a reward screen.
+#[derive(Clone, Debug)]
+pub struct RewardDetection {
+    pub rewards: Vec<Reward>,
+    /// The theme the rewards were read with.
+    pub theme: Theme,
+    /// Share of the read names that matched an item, 0 when nothing was read.
+    pub confidence: f32,
+}
+
+/// Matches the names read from the reward slots, skipping empty slots. Returns the rewards and
+/// the share of read names that matched one.
+pub fn match_reward_names(database: &Database, names: &[String]) -> (Vec<Reward>, f32) {
+    let read: Vec<_> = names
+        .iter()
+        .filter(|name| !normalize_string(name).is_empty())
+        .collect();
+    let rewards: Vec<_> = read
+        .iter()
+        .filter_map(|name| database.find_reward(name))
+        .collect();
+    let confidence = if read.is_empty() {
+        0.0
+    } else {
+        rewards.len() as f32 / read.len() as f32
+    };
+    (rewards, confidence)
+}
+
+fn read_rewards(image: &DynamicImage, theme: Theme, database: &Database) -> RewardDetection {
+    let names = reward_image_to_reward_names(image.clone(), Some(theme.clone()));
+    debug!("{} read {:?}", theme.name, names);
+    let (rewards, confidence) = match_reward_names(database, &names);
+    RewardDetection {
+        rewards,
+        theme,
+        confidence,
+    }
+}
+
+/// Reads the rewards of a reward screen. Without a theme the detected one is used, and when few
+/// of the names read with it match an item they are read again with the runner-up theme, keeping
+/// whichever matched more.
+pub fn reward_image_to_rewards(
+    image: &DynamicImage,
+    theme: Option<Theme>,
+    database: &Database,
+) -> RewardDetection {
+    let ranking = match theme {
+        Some(theme) => return read_rewards(image, theme, database),
+        None => detect_theme(image),
+    };
+    let first = read_rewards(image, ranking.best().clone(), database);
+    if first.confidence >= MIN_MATCH_CONFIDENCE {
+        return first;
+    }
+    let Some(runner_up) = ranking.runner_up() else {
+        return first;
+    };
+    debug!(
+        "Only {:.0}% matched with {}, retrying with {}",
+        first.confidence * 100.0,
+        first.theme.name,
+        runner_up.name
+    );
+    let second = read_rewards(image, runner_up.clone(), database);
+    if second.confidence > first.confidence {
+        second
+    } else {
+        first
+    }
+}
+
+/// A relic a squad member chose on the void projection screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SquadRelic {
+    pub relic: RelicId,
+    pub refinement: Refinement,
+}
+
+impl SquadRelic {
+    pub fn from_ocr(text: &str, relics: &Relics) -> Option<SquadRelic> {
+        Some(SquadRelic {
+            relic: RelicId::from_ocr(text, relics)?,
+            refinement: Refinement::from_ocr(text),
+        })
+    }
+}
+
+/// Cuts the relic names out of the void projection screen, one filtered image per squad member
+/// that picked a relic.
+pub fn extract_squad_relics(image: &DynamicImage, theme: Theme) -> Vec<DynamicImage> {
+    let screen_scaling = if image.width() * 9 > image.height() * 16 {
+        image.height() as f32 / 1080.0
+    } else {
+        image.width() as f32 / 1920.0
+    };
+
+    let width = PIXEL_SQUAD_RELIC_WIDTH * screen_scaling;
+    let left = image.width() as f32 / 2.0 - width / 2.0;
+    let top = image.height() as f32 / 2.0 + (PIXEL_SQUAD_RELIC_TOP - 540.0) * screen_scaling;
+    let height = PIXEL_SQUAD_RELIC_HEIGHT * screen_scaling;
+
+    let mut filtered = image
+        .crop_imm(left as u32, top as u32, width as u32, height as u32)
+        .into_rgb8();
+    for pixel in filtered.pixels_mut() {
+        *pixel = if theme.threshold_filter(*pixel) {
+            Rgb([0; 3])
+        } else {
+            Rgb([255; 3])
+        };
+    }
+
+    let box_width = filtered.width() / SQUAD_SIZE;
+    let box_height = filtered.height();
+    // Empty slots have no text, a name covers at least a few percent of its box
+    let min_text_pixels = box_width * box_height / 50;
+
+    let dynamic_image = DynamicImage::ImageRgb8(filtered);
+    (0..SQUAD_SIZE)
+        .map(|i| dynamic_image.crop_imm(i * box_width, 0, box_width, box_height))
+        .filter(|cropped| {
+            let text_pixels = cropped
+                .to_rgb8()
+                .pixels()
+                .filter(|pixel| pixel.0 == [0; 3])
+                .count();
+            text_pixels as u32 >= min_text_pixels
+        })
+        .collect()
+}
+
+/// Reads the relic and refinement every squad member chose, skipping names that do not match a
+/// known relic.
+pub fn relic_selection_image_to_relics(
+    image: DynamicImage,
+    theme: Option<Theme>,
+    relics: &Relics,
+) -> Vec<SquadRelic> {
+    let theme = theme.unwrap_or_else(|| detect_theme(&image).into_best());
+    let names = extract_squad_relics(&image, theme);
+    debug!("Extracted {} squad relic images", names.len());
+
+    names
+        .iter()
+        .map(|image| image_to_string(&mut OCR.lock().unwrap(), image))
+        .filter_map(|text| {
+            let relic = SquadRelic::from_ocr(&text, relics);
+            debug!("{:?} -> {:?}", text, relic);
+            relic
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reward_matches_are_scored() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let names: Vec<_> = ["Burston Prime Stock", "200 X Kuva", " \n", "Zzqx Vvkw"]
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        let (rewards, confidence) = match_reward_names(&database, &names);
+        let rewards: Vec<_> = rewards.iter().map(Reward::to_string).collect();
+        assert_eq!(rewards, ["Burston Prime Stock", "Kuva x200"]);
+        assert_eq!(confidence, 2.0 / 3.0);
+        assert_eq!(match_reward_names(&database, &[]).1, 0.0);
+    }
+}
@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use image::DynamicImage;
+use log::debug;
+
+use crate::{
+    database::{Database, Item},
+    ocr::{detect_theme, filter_text, image_to_string, normalize_string, OCR},
+    theme::Theme,
+};
+
+/// A rectangle of a screenshot, in pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// The rectangle spanned by two opposite corners, e.g. where a drag started and ended.
+    pub fn from_corners(a: (u32, u32), b: (u32, u32)) -> Region {
+        Region {
+            x: a.0.min(b.0),
+            y: a.1.min(b.1),
+            width: a.0.abs_diff(b.0),
+            height: a.1.abs_diff(b.1),
+        }
+    }
+
+    pub fn fits(&self, image: &DynamicImage) -> bool {
+        let fits = |start: u32, length: u32, limit: u32| {
+            start.checked_add(length).is_some_and(|end| end <= limit)
+        };
+        fits(self.x, self.width, image.width()) && fits(self.y, self.height, image.height())
+    }
+
+    /// The whole image.
+    pub fn of(image: &DynamicImage) -> Region {
+        Region {
+            x: 0,
+            y: 0,
+            width: image.width(),
+            height: image.height(),
+        }
+    }
+}
+
+/// Parses regions written as "x,y,width,height".
+impl FromStr for Region {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numbers = s
+            .split(',')
+            .map(|number| number.trim().parse())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| anyhow!("Expected a region like \"100,200,640,480\", got: {s}"))?;
+        let [x, y, width, height] = numbers[..] else {
+            bail!("Expected a region like \"100,200,640,480\", got: {s}");
+        };
+        if width == 0 || height == 0 {
+            bail!("Region is empty: {s}");
+        }
+        Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+}
+
+/// Reads the text lines in a region of a screenshot. Without a theme it is detected from the
+/// whole screenshot.
+pub fn snap_text(image: &DynamicImage, region: Region, theme: Option<Theme>) -> Vec<String> {
+    let theme = theme.unwrap_or_else(|| detect_theme(image).into_best());
+    let cropped = image.crop_imm(region.x, region.y, region.width, region.height);
+    let (filtered, text_pixels) = filter_text(&cropped, &theme);
+    if text_pixels == 0 {
+        return Vec::new();
+    }
+    let text = image_to_string(&mut OCR.lock().unwrap(), &filtered);
+    debug!("Snapped text: {:?}", text);
+    text.lines().map(str::to_owned).collect()
+}
+
+/// Looks up every item named in the text lines, in order and without repeats. Names wrapped onto
+/// a second line are joined first.
+pub fn match_snap_text<'a>(lines: &[String], database: &'a Database) -> Vec<&'a Item> {
+    let lines: Vec<_> = lines
+        .iter()
+        .map(|line| normalize_string(line))
+        .filter(|line| line.len() >= 3)
+        .collect();
+
+    let mut items: Vec<&Item> = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let joined = lines
+            .get(index + 1)
+            .and_then(|next| database.find_item(&format!("{}{}", lines[index], next), None));
+        let item = match joined {
+            Some(item) => {
+                index += 2;
+                Some(item)
+            }
+            None => {
+                index += 1;
+                database.find_item(&lines[index - 1], None)
+            }
+        };
+        if let Some(item) = item {
+            if !items.iter().any(|found| found.name == item.name) {
+                items.push(item);
+            }
+        }
+    }
+    items
+}
+
+/// Looks up the items named in a region of a screenshot.
+pub fn snap_items<'a>(
+    image: &DynamicImage,
+    region: Region,
+    theme: Option<Theme>,
+    database: &'a Database,
+) -> Vec<&'a Item> {
+    match_snap_text(&snap_text(image, region, theme), database)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_parse_regions() {
+        let region: Region = "100, 200,640,480".parse().unwrap();
+        assert_eq!(
+            region,
+            Region {
+                x: 100,
+                y: 200,
+                width: 640,
+                height: 480
+            }
+        );
+        assert_eq!(Region::from_corners((740, 200), (100, 680)), region);
+        assert!("100,200,640".parse::<Region>().is_err());
+        assert!("100,200,0,480".parse::<Region>().is_err());
+        assert!("a,b,c,d".parse::<Region>().is_err());
+
+        let image = DynamicImage::new_rgb8(1920, 1080);
+        assert!(region.fits(&image));
+        assert!(Region::of(&image).fits(&image));
+        assert!(!"1900,0,21,1".parse::<Region>().unwrap().fits(&image));
+        assert!(!"4294967295,0,1,1".parse::<Region>().unwrap().fits(&image));
+        assert!(!"0,1,1,4294967295".parse::<Region>().unwrap().fits(&image));
+    }
+
+    #[test]
+    fn wrapped_names_are_joined() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let lines: Vec<String> = [
+            "Titania Prime Systems",
+            "Blueprint",
+            "",
+            "Burston Prime Stock",
+            "~~",
+            "Burston Prime Stock",
+        ]
+        .into_iter()
+        .map(str::to_owned)
+        .collect();
+        let names: Vec<_> = match_snap_text(&lines, &database)
+            .into_iter()
+            .map(|item| item.name.as_str())
+            .collect();
+        assert_eq!(names, ["Titania Prime Systems", "Burston Prime Stock"]);
+    }
+}
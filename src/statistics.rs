@@ -1,3 +1,5 @@
+use fraction::BigFraction;
+
 #[derive(Copy, Clone, Debug)]
 pub struct Item {
     pub value: f32,
@@ -43,14 +45,223 @@ impl Bucket {
 
         total_expectation
     }
+
+    fn cumulative_probability_at(&self, value: f32) -> f32 {
+        self.items
+            .iter()
+            .take_while(|item| item.value <= value)
+            .map(|item| item.probability)
+            .sum()
+    }
+
+    /// Expected value of the best item when drawing once from each of the buckets, which may
+    /// all have different distributions.
+    pub fn expectation_of_best_of_each(buckets: &[Bucket]) -> f32 {
+        Self::distribution_of_best_of_each(buckets).mean()
+    }
+
+    pub fn distribution_of_best_of_n(&self, n: u32) -> Distribution {
+        Self::distribution_of_best_of_each(&vec![self.clone(); n as usize])
+    }
+
+    /// Probability mass function of the best item when drawing once from each of the buckets.
+    pub fn distribution_of_best_of_each(buckets: &[Bucket]) -> Distribution {
+        let mut values: Vec<f32> = buckets
+            .iter()
+            .flat_map(|bucket| bucket.items.iter().map(|item| item.value))
+            .collect();
+        values.sort_by(|a, b| a.total_cmp(b));
+        values.dedup();
+
+        let mut previous_probability = 0.0;
+        let outcomes = values
+            .into_iter()
+            .map(|value| {
+                let cumulative_probability: f32 = buckets
+                    .iter()
+                    .map(|bucket| bucket.cumulative_probability_at(value))
+                    .product();
+                let just_this_probability = cumulative_probability - previous_probability;
+                previous_probability = cumulative_probability;
+                Item {
+                    value,
+                    probability: just_this_probability,
+                }
+            })
+            .collect();
+
+        Distribution { outcomes }
+    }
+}
+
+/// Exact counterpart of [`Item`], with the value and probability as rational numbers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExactItem {
+    pub value: BigFraction,
+    pub probability: BigFraction,
+}
+
+impl From<Item> for ExactItem {
+    /// Converts through the shortest decimal representation, so 0.2533 becomes 2533/10000.
+    fn from(item: Item) -> Self {
+        ExactItem {
+            value: BigFraction::from(item.value),
+            probability: BigFraction::from(item.probability),
+        }
+    }
+}
+
+/// Exact counterpart of [`Bucket`] without any rounding errors. Much slower, meant for verifying
+/// the floating point results.
+#[derive(Clone, Debug)]
+pub struct ExactBucket {
+    items: Vec<ExactItem>,
+}
+
+impl From<&Bucket> for ExactBucket {
+    fn from(bucket: &Bucket) -> Self {
+        ExactBucket::new(bucket.items.iter().copied().map(ExactItem::from).collect())
+    }
+}
+
+impl ExactBucket {
+    pub fn new(mut items: Vec<ExactItem>) -> Self {
+        items.sort_by(|a, b| a.value.cmp(&b.value));
+        Self { items }
+    }
+
+    fn cumulative_probability_at(&self, value: &BigFraction) -> BigFraction {
+        self.items
+            .iter()
+            .take_while(|item| &item.value <= value)
+            .map(|item| &item.probability)
+            .sum()
+    }
+
+    pub fn expectation_of_best_of_n(&self, n: u32) -> BigFraction {
+        Self::expectation_of_best_of_each(&vec![self.clone(); n as usize])
+    }
+
+    /// Expected value of the best item when drawing once from each of the buckets.
+    pub fn expectation_of_best_of_each(buckets: &[ExactBucket]) -> BigFraction {
+        let mut values: Vec<&BigFraction> = buckets
+            .iter()
+            .flat_map(|bucket| bucket.items.iter().map(|item| &item.value))
+            .collect();
+        values.sort();
+        values.dedup();
+
+        let mut total_expectation = BigFraction::from(0);
+        let mut previous_probability = BigFraction::from(0);
+        for value in values {
+            let cumulative_probability: BigFraction = buckets
+                .iter()
+                .map(|bucket| bucket.cumulative_probability_at(value))
+                .product();
+            total_expectation +=
+                (cumulative_probability.clone() - previous_probability) * value.clone();
+            previous_probability = cumulative_probability;
+        }
+
+        total_expectation
+    }
+}
+
+/// A discrete distribution of distinct values, sorted from lowest to highest.
+#[derive(Clone, Debug)]
+pub struct Distribution {
+    outcomes: Vec<Item>,
+}
+
+impl Distribution {
+    pub fn outcomes(&self) -> &[Item] {
+        &self.outcomes
+    }
+
+    pub fn mean(&self) -> f32 {
+        self.outcomes
+            .iter()
+            .map(|item| item.value * item.probability)
+            .sum()
+    }
+
+    pub fn variance(&self) -> f32 {
+        let mean = self.mean();
+        self.outcomes
+            .iter()
+            .map(|item| (item.value - mean).powi(2) * item.probability)
+            .sum()
+    }
+
+    pub fn standard_deviation(&self) -> f32 {
+        self.variance().sqrt()
+    }
+
+    /// Smallest value that is reached or undercut with at least the given probability.
+    pub fn percentile(&self, probability: f32) -> f32 {
+        let mut cumulative_probability = 0.0;
+        for item in self.outcomes.iter() {
+            cumulative_probability += item.probability;
+            if cumulative_probability >= probability - 1e-6 {
+                return item.value;
+            }
+        }
+        self.outcomes.last().map(|item| item.value).unwrap_or(0.0)
+    }
+
+    pub fn median(&self) -> f32 {
+        self.percentile(0.5)
+    }
+
+    pub fn probability_at_least(&self, value: f32) -> f32 {
+        self.outcomes
+            .iter()
+            .filter(|item| item.value >= value)
+            .map(|item| item.probability)
+            .sum()
+    }
 }
 
 #[cfg(test)]
 mod test {
     use approx::assert_relative_eq;
+    use fraction::ToPrimitive;
 
     use super::*;
 
+    /// Small xorshift generator, so the property tests are reproducible without extra crates.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, limit: u64) -> u64 {
+            self.next() % limit
+        }
+
+        /// A bucket of 1 to 8 items with prices in steps of 0.5 and probabilities adding up to 1.
+        fn bucket(&mut self) -> Bucket {
+            let weights: Vec<u64> = (0..1 + self.below(8))
+                .map(|_| 1 + self.below(100))
+                .collect();
+            let total: u64 = weights.iter().sum();
+            Bucket::new(
+                weights
+                    .into_iter()
+                    .map(|weight| Item {
+                        value: self.below(400) as f32 / 2.0,
+                        probability: weight as f32 / total as f32,
+                    })
+                    .collect(),
+            )
+        }
+    }
+
     fn uniform(values: Vec<f32>) -> Vec<Item> {
         let probability = 1.0 / values.len() as f32;
         values
@@ -104,4 +315,144 @@ mod test {
         let value = bucket.expectation_of_best_of_n(2);
         assert_relative_eq!(value, 0.1 + 0.9 * 0.1);
     }
+
+    #[test]
+    fn die_and_coin() {
+        let die = Bucket::new(uniform(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        let coin = Bucket::new(uniform(vec![1.0, 2.0]));
+        let value = Bucket::expectation_of_best_of_each(&[die, coin]);
+        assert_relative_eq!(value, 43.0 / 12.0, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn single_die_distribution() {
+        let bucket = Bucket::new(uniform(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        let distribution = bucket.distribution_of_best_of_n(1);
+        assert_eq!(distribution.outcomes().len(), 6);
+        assert_relative_eq!(distribution.mean(), 3.5, epsilon = 0.0001);
+        assert_relative_eq!(distribution.variance(), 35.0 / 12.0, epsilon = 0.0001);
+        assert_relative_eq!(distribution.median(), 3.0);
+        assert_relative_eq!(distribution.percentile(0.9), 6.0);
+        assert_relative_eq!(
+            distribution.probability_at_least(5.0),
+            1.0 / 3.0,
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn two_dies_distribution() {
+        let bucket = Bucket::new(uniform(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        let distribution = bucket.distribution_of_best_of_n(2);
+        for item in distribution.outcomes() {
+            assert_relative_eq!(
+                item.probability,
+                (2.0 * item.value - 1.0) / 36.0,
+                epsilon = 0.0001
+            );
+        }
+        assert_relative_eq!(distribution.mean(), 161.0 / 36.0, epsilon = 0.0001);
+        assert_relative_eq!(
+            distribution.variance(),
+            791.0 / 36.0 - (161.0f32 / 36.0).powi(2),
+            epsilon = 0.0001
+        );
+        assert_relative_eq!(distribution.median(), 5.0);
+        assert_relative_eq!(
+            distribution.probability_at_least(6.0),
+            11.0 / 36.0,
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn odd_coins_distribution() {
+        let bucket = Bucket::new(vec![
+            Item {
+                value: 0.0,
+                probability: 0.9,
+            },
+            Item {
+                value: 1.0,
+                probability: 0.1,
+            },
+        ]);
+        let distribution = bucket.distribution_of_best_of_n(2);
+        assert_relative_eq!(distribution.median(), 0.0);
+        assert_relative_eq!(
+            distribution.probability_at_least(1.0),
+            0.19,
+            epsilon = 0.0001
+        );
+        assert_relative_eq!(distribution.variance(), 0.19 * 0.81, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn identical_buckets_match_best_of_n() {
+        let die = Bucket::new(uniform(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        for n in 1..=4 {
+            let buckets = vec![die.clone(); n];
+            assert_relative_eq!(
+                Bucket::expectation_of_best_of_each(&buckets),
+                die.expectation_of_best_of_n(n as u32),
+                epsilon = 0.0001
+            );
+        }
+    }
+
+    #[test]
+    fn exact_die_and_coin() {
+        let die = ExactBucket::new(
+            (1..=6)
+                .map(|value| ExactItem {
+                    value: BigFraction::from(value),
+                    probability: BigFraction::new(1u64, 6u64),
+                })
+                .collect(),
+        );
+        let coin = ExactBucket::new(
+            (1..=2)
+                .map(|value| ExactItem {
+                    value: BigFraction::from(value),
+                    probability: BigFraction::new(1u64, 2u64),
+                })
+                .collect(),
+        );
+        assert_eq!(
+            ExactBucket::expectation_of_best_of_each(&[die.clone(), coin]),
+            BigFraction::new(43u64, 12u64)
+        );
+        assert_eq!(
+            die.expectation_of_best_of_n(2),
+            BigFraction::new(161u64, 36u64)
+        );
+    }
+
+    #[test]
+    fn float_matches_exact_for_random_buckets() {
+        let mut random = Random(0x5eed_1234_abcd_ef01);
+        for _ in 0..100 {
+            let bucket = random.bucket();
+            let exact = ExactBucket::from(&bucket);
+            for n in 1..=4 {
+                assert_relative_eq!(
+                    bucket.expectation_of_best_of_n(n) as f64,
+                    exact.expectation_of_best_of_n(n).to_f64().unwrap(),
+                    epsilon = 0.001,
+                    max_relative = 0.0001
+                );
+            }
+
+            let buckets: Vec<Bucket> = (0..1 + random.below(4)).map(|_| random.bucket()).collect();
+            let exact_buckets: Vec<ExactBucket> = buckets.iter().map(ExactBucket::from).collect();
+            assert_relative_eq!(
+                Bucket::expectation_of_best_of_each(&buckets) as f64,
+                ExactBucket::expectation_of_best_of_each(&exact_buckets)
+                    .to_f64()
+                    .unwrap(),
+                epsilon = 0.001,
+                max_relative = 0.0001
+            );
+        }
+    }
 }
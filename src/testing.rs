@@ -4,6 +4,7 @@ use crate::theme::Theme;
 
 #[derive(Serialize, Deserialize)]
 pub struct Label {
+    #[serde(with = "crate::theme::by_name")]
     pub theme: Theme,
     pub items: Vec<String>,
 }
@@ -1,10 +1,26 @@
-use std::ops::Range;
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::anyhow;
 
 use image::Rgb;
+use lazy_static::lazy_static;
+use log::warn;
 use ordered_float::OrderedFloat;
 use palette::{FromColor, Hsl, RgbHue, Srgb};
 use serde::{Deserialize, Serialize};
 
+use crate::utils::data_directory;
+
+/// Hue in degrees, saturation and lightness ranges. The starts are inclusive and the ends
+/// exclusive, an end past the largest value (180 for the hue, 1 otherwise) makes a range
+/// unbounded.
 #[derive(Clone, Debug, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub struct HslRange<T> {
     pub hue: Range<T>,
@@ -20,6 +36,20 @@ impl HslRange<OrderedFloat<f32>> {
             ((self.lightness.start + self.lightness.end) / 2.0).0,
         ))
     }
+
+    pub fn contains(&self, color: Hsl) -> bool {
+        self.hue.contains(&OrderedFloat(color.hue.to_degrees()))
+            && self.saturation.contains(&OrderedFloat(color.saturation))
+            && self.lightness.contains(&OrderedFloat(color.lightness))
+    }
+
+    pub fn to_unordered(&self) -> HslRange<f32> {
+        HslRange {
+            hue: self.hue.start.0..self.hue.end.0,
+            saturation: self.saturation.start.0..self.saturation.end.0,
+            lightness: self.lightness.start.0..self.lightness.end.0,
+        }
+    }
 }
 
 impl HslRange<f32> {
@@ -32,24 +62,26 @@ impl HslRange<f32> {
     }
 }
 
+/// The built-in themes, see [`Theme::iter`].
+pub const DEFAULT_THEMES: &str = include_str!("themes.json");
+
+fn default_tolerance() -> OrderedFloat<f32> {
+    OrderedFloat(0.2)
+}
+
+/// Colours of an in-game UI theme and how its text is told apart from the background.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub enum Theme {
-    Vitruvian,
-    Stalker,
-    Baruuk,
-    Corpus,
-    Fortuna,
-    Grineer,
-    Lotus,
-    Nidus,
-    Orokin,
-    Tenno,
-    HighContrast,
-    Legacy,
-    Equinox,
-    DarkLotus,
-    Zephyr,
-    Custom(HslRange<OrderedFloat<f32>>),
+pub struct Theme {
+    pub name: String,
+    pub primary: [u8; 3],
+    pub secondary: [u8; 3],
+    /// Text pixels fall in this range. Without one, pixels close to the primary or secondary
+    /// colour are text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<HslRange<OrderedFloat<f32>>>,
+    /// Largest [`color_difference`] of a text pixel from the primary or secondary colour.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: OrderedFloat<f32>,
 }
 
 pub fn color_difference(colors: (Hsl, Hsl)) -> f32 {
@@ -59,174 +91,475 @@ pub fn color_difference(colors: (Hsl, Hsl)) -> f32 {
         * 255.0
 }
 
+fn to_hsl(color: [u8; 3]) -> Hsl {
+    Hsl::from_color(Srgb::new(color[0], color[1], color[2]).into_format::<f32>())
+}
+
+fn to_rgb(color: Hsl) -> [u8; 3] {
+    let rgb: Srgb<u8> = Srgb::from_color(color).into_format();
+    [rgb.red, rgb.green, rgb.blue]
+}
+
+/// Index of the loaded theme with the closest primary colour, and their [`color_difference`].
+fn closest_reference(color: Rgb<u8>) -> (usize, f32) {
+    // Through HSL like the colours in closest_from_color, so the differences are the same
+    let rgb: Srgb = Srgb::from_color(to_hsl(color.0));
+    REFERENCE_COLORS
+        .iter()
+        .map(|reference| {
+            ((reference.red - rgb.red).abs()
+                + (reference.green - rgb.green).abs()
+                + (reference.blue - rgb.blue).abs())
+                * 255.0
+        })
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+}
+
+/// Where the user's themes are read from, see [`Theme::iter`].
+pub fn user_themes_path() -> PathBuf {
+    data_directory().join("themes.json")
+}
+
+/// Replaces the themes with the same name as an override and appends the others.
+pub fn merge_themes(themes: &mut Vec<Theme>, overrides: Vec<Theme>) {
+    for theme in overrides {
+        match themes.iter_mut().find(|known| known.name == theme.name) {
+            Some(known) => *known = theme,
+            None => themes.push(theme),
+        }
+    }
+}
+
+/// Reads a themes.json, which is empty when the file does not exist.
+pub fn load_user_themes(path: &Path) -> Result<Vec<Theme>, anyhow::Error> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(serde_json::from_str(&text)?),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Adds a theme to a themes.json, replacing the one with the same name.
+pub fn save_user_theme(path: &Path, theme: Theme) -> Result<(), anyhow::Error> {
+    let mut themes = load_user_themes(path)?;
+    merge_themes(&mut themes, vec![theme]);
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&themes)?)?;
+    Ok(())
+}
+
+fn load_themes() -> Vec<Theme> {
+    let mut themes = serde_json::from_str(DEFAULT_THEMES).expect("Invalid built-in themes");
+    let path = user_themes_path();
+    match load_user_themes(&path) {
+        Ok(overrides) => merge_themes(&mut themes, overrides),
+        Err(err) => warn!("Ignoring invalid themes in {}: {err}", path.display()),
+    }
+    themes
+}
+
+lazy_static! {
+    static ref THEMES: Vec<Theme> = load_themes();
+    /// The primary colour of every loaded theme, in the same order, as compared by
+    /// [`color_difference`].
+    static ref REFERENCE_COLORS: Vec<Srgb> = THEMES
+        .iter()
+        .map(|theme| Srgb::from_color(theme.primary()))
+        .collect();
+}
+
+/// Low bits dropped from every channel when counting colours, so shades that differ only by
+/// noise or anti-aliasing share a bin.
+const HISTOGRAM_DROPPED_BITS: u32 = 2;
+
+/// Pixels of similar colours, see [`ColorHistogram`].
+#[derive(Clone, Copy, Debug, Default)]
+struct ColorBin {
+    count: u32,
+    sum: [u64; 3],
+}
+
+impl ColorBin {
+    /// The average colour of the pixels in the bin.
+    fn average(&self) -> Rgb<u8> {
+        let count = u64::from(self.count);
+        Rgb(self.sum.map(|sum| ((sum + count / 2) / count) as u8))
+    }
+}
+
+/// Number of pixels of every colour in an image or part of it, quantised to
+/// [`HISTOGRAM_DROPPED_BITS`] fewer bits per channel. Every bin keeps the average of its colours.
+#[derive(Clone, Debug, Default)]
+pub struct ColorHistogram(HashMap<[u8; 3], ColorBin>);
+
+impl ColorHistogram {
+    pub fn add(&mut self, color: Rgb<u8>) {
+        let bin = self
+            .0
+            .entry(color.0.map(|channel| channel >> HISTOGRAM_DROPPED_BITS))
+            .or_default();
+        bin.count += 1;
+        for (sum, channel) in bin.sum.iter_mut().zip(color.0) {
+            *sum += u64::from(channel);
+        }
+    }
+
+    /// Number of bins in use.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromIterator<Rgb<u8>> for ColorHistogram {
+    fn from_iter<T: IntoIterator<Item = Rgb<u8>>>(iter: T) -> Self {
+        let mut histogram = ColorHistogram::default();
+        for color in iter {
+            histogram.add(color);
+        }
+        histogram
+    }
+}
+
 impl Theme {
-    pub fn closest_from_color(color: Rgb<u8>) -> (Theme, f32) {
-        let rgb = Srgb::from_components((
-            color.0[0] as f32 / 255.0,
-            color.0[1] as f32 / 255.0,
-            color.0[2] as f32 / 255.0,
-        ));
-        let hsl = Hsl::from_color(rgb);
+    /// A theme that only uses a hand tuned filter, e.g. from the theme_tune tool.
+    pub fn custom(filter: HslRange<OrderedFloat<f32>>) -> Theme {
+        let average = to_rgb(filter.get_average());
+        Theme {
+            name: "Custom".to_string(),
+            primary: average,
+            secondary: average,
+            filter: Some(filter),
+            tolerance: default_tolerance(),
+        }
+    }
+
+    /// The theme with its filter replaced. The colours are kept, so it's still detected the same.
+    pub fn with_filter(&self, filter: HslRange<OrderedFloat<f32>>) -> Theme {
+        Theme {
+            filter: Some(filter),
+            ..self.clone()
+        }
+    }
+
+    /// The theme's filter, or for themes matching their colours a range spanning both colours
+    /// with some margin. A starting point for tuning a custom filter.
+    pub fn approximate_filter(&self) -> HslRange<OrderedFloat<f32>> {
+        if let Some(filter) = &self.filter {
+            return filter.clone();
+        }
+        let (primary, secondary) = (self.primary(), self.secondary());
+        let span = |a: f32, b: f32, margin: f32, min: f32, max: f32| {
+            OrderedFloat((a.min(b) - margin).max(min))..OrderedFloat((a.max(b) + margin).min(max))
+        };
+        HslRange {
+            hue: span(
+                primary.hue.to_degrees(),
+                secondary.hue.to_degrees(),
+                10.0,
+                -180.0,
+                181.0,
+            ),
+            saturation: span(primary.saturation, secondary.saturation, 0.1, 0.0, 1.01),
+            lightness: span(primary.lightness, secondary.lightness, 0.1, 0.0, 1.01),
+        }
+    }
+
+    /// The loaded theme with the given name.
+    pub fn named(name: &str) -> Option<Theme> {
+        Self::iter().find(|theme| theme.name == name).cloned()
+    }
+
+    /// The theme with the primary colour closest to the colour, and their difference. See
+    /// [`ThemeRanking::from_histogram`] for ranking many colours.
+    pub fn closest_from_color(color: Rgb<u8>) -> (&'static Theme, f32) {
+        let hsl = to_hsl(color.0);
         Self::iter()
-            .map(|theme| (theme.clone(), color_difference((theme.primary(), hsl))))
+            .map(|theme| (theme, color_difference((theme.primary(), hsl))))
             .min_by(|a, b| a.1.total_cmp(&b.1))
             .unwrap()
     }
 
+    /// The built-in themes, with those in the user's themes.json replacing the built-in ones of
+    /// the same name or added after them. Loaded once on first use.
     pub fn iter() -> std::slice::Iter<'static, Theme> {
-        [
-            Self::Vitruvian,
-            Self::Stalker,
-            Self::Baruuk,
-            Self::Corpus,
-            Self::Fortuna,
-            Self::Grineer,
-            Self::Lotus,
-            Self::Nidus,
-            Self::Orokin,
-            Self::Tenno,
-            Self::HighContrast,
-            Self::Legacy,
-            Self::Equinox,
-            Self::DarkLotus,
-            Self::Zephyr,
-        ]
-        .iter()
+        THEMES.iter()
     }
 
     pub fn threshold_filter(&self, color: Rgb<u8>) -> bool {
-        let rgb = Srgb::from_components((
-            color.0[0] as f32 / 255.0,
-            color.0[1] as f32 / 255.0,
-            color.0[2] as f32 / 255.0,
-        ));
-        let test = Hsl::from_color(rgb);
+        let test = to_hsl(color.0);
+        match &self.filter {
+            Some(range) => range.contains(test),
+            None => {
+                color_difference((self.primary(), test)) < self.tolerance.0
+                    || color_difference((self.secondary(), test)) < self.tolerance.0
+            }
+        }
+    }
 
-        let primary = self.primary();
-        let secondary = self.secondary();
+    pub fn primary(&self) -> Hsl {
+        to_hsl(self.primary)
+    }
 
-        match self {
-            Theme::Equinox => test.saturation <= 0.2 && test.lightness >= 0.55,
-            Theme::Stalker => {
-                (0.61..1.00).contains(&test.saturation)
-                    && (0.25..0.65).contains(&test.lightness)
-                    && (-10.0..5.0).contains(&test.hue.to_degrees())
-            }
-            Theme::HighContrast => {
-                test.saturation >= 0.60
-                    && (0.23..0.45).contains(&test.lightness)
-                    && (-160.0..-145.0).contains(&test.hue.to_degrees())
-            }
-            Theme::Custom(range) => {
-                range.hue.contains(&OrderedFloat(test.hue.to_degrees()))
-                    && range.saturation.contains(&OrderedFloat(test.saturation))
-                    && range.lightness.contains(&OrderedFloat(test.lightness))
+    pub fn secondary(&self) -> Hsl {
+        to_hsl(self.secondary)
+    }
+}
+
+/// Themes ordered from the best to the worst match, with scores that sum to 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThemeRanking(pub Vec<(Theme, f32)>);
+
+impl ThemeRanking {
+    /// Ranks themes by their accumulated weights. Themes with equal weights are ordered by name.
+    pub fn from_weights<'a>(weights: impl IntoIterator<Item = (&'a Theme, f32)>) -> ThemeRanking {
+        let mut ranking: Vec<_> = weights
+            .into_iter()
+            .map(|(theme, weight)| (theme.clone(), weight))
+            .collect();
+        let total = ranking.iter().fold(0.0, |sum, (_, weight)| sum + weight);
+        if total > 0.0 {
+            for (_, weight) in ranking.iter_mut() {
+                *weight /= total;
             }
-            _ => {
-                color_difference((primary, test)) < 0.2 || color_difference((secondary, test)) < 0.2
+        }
+        ranking.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.name.cmp(&b.0.name)));
+        ThemeRanking(ranking)
+    }
+
+    /// Ranks the themes like [`Theme::closest_from_color`] weighted for every pixel, but
+    /// comparing the average colour of each histogram bin only once. Themes closest to no bin
+    /// are left out.
+    pub fn from_histogram(histogram: &ColorHistogram) -> ThemeRanking {
+        let mut weights = vec![0.0; REFERENCE_COLORS.len()];
+        for bin in histogram.0.values() {
+            let (index, difference) = closest_reference(bin.average());
+            weights[index] += bin.count as f32 / (1.0 + difference).powi(4);
+        }
+        ThemeRanking::from_weights(
+            THEMES
+                .iter()
+                .zip(weights)
+                .filter(|(_, weight)| *weight > 0.0),
+        )
+    }
+
+    pub fn best(&self) -> &Theme {
+        &self.0.first().expect("No theme was ranked").0
+    }
+
+    pub fn into_best(self) -> Theme {
+        self.0.into_iter().next().expect("No theme was ranked").0
+    }
+
+    pub fn runner_up(&self) -> Option<&Theme> {
+        self.0.get(1).map(|(theme, _)| theme)
+    }
+}
+
+/// Parses the name of a loaded theme.
+impl FromStr for Theme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Theme::named(s).ok_or_else(|| {
+            let names: Vec<_> = Theme::iter().map(|theme| theme.name.as_str()).collect();
+            anyhow!("Unknown theme: {s}, expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+/// (De)serializes a theme as just its name, looked up in the loaded themes.
+pub mod by_name {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::Theme;
+
+    pub fn serialize<S: Serializer>(theme: &Theme, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&theme.name)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Theme, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Theme::named(&name).ok_or_else(|| D::Error::custom(format!("Unknown theme: {name}")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Label;
+
+    #[test]
+    fn built_in_themes_are_loaded() {
+        let themes: Vec<Theme> = serde_json::from_str(DEFAULT_THEMES).unwrap();
+        assert_eq!(themes.len(), 15);
+        for theme in &themes {
+            assert_eq!(themes.iter().filter(|t| t.name == theme.name).count(), 1);
+            if theme.filter.is_none() {
+                assert!(theme.threshold_filter(Rgb(theme.primary)));
+                assert!(theme.threshold_filter(Rgb(theme.secondary)));
             }
         }
 
-        // hsv.hue.abs_diff_eq(&primary.hue, 4.0) && hsv.saturation >= 0.25 && hsv.value >= 0.42
-
-        // match self {
-        //     Theme::Vitruvian => {
-        //         test.hue.abs_diff_eq(&primary.hue, 4.0)
-        //             && test.saturation >= 0.25
-        //             && test.lightness >= 0.42
-        //     }
-        //     Theme::Stalker => {
-        //         test.hue.abs_diff_eq(&primary.hue, 4.0) && test.saturation >= 0.55
-        //             || test.hue.abs_diff_eq(&secondary.hue, 4.0)
-        //                 && test.saturation >= 0.66
-        //                 && test.lightness >= 0.25
-        //     }
-        //     Theme::Baruuk => {
-        //         test.hue.abs_diff_eq(&primary.hue, 2.0)
-        //             && test.saturation > 0.25
-        //             && test.lightness > 0.5
-        //     }
-        //     Theme::Corpus => {
-        //         test.hue.abs_diff_eq(&primary.hue, 3.0)
-        //             && test.saturation >= 0.35
-        //             && test.lightness >= 0.42
-        //     }
-        //     Theme::Fortuna => {
-        //         test.hue.abs_diff_eq(&primary.hue, 3.0) && test.lightness >= 0.35
-        //             || test.hue.abs_diff_eq(&secondary.hue, 4.0)
-        //                 && test.saturation >= 0.2
-        //                 && test.lightness >= 0.15
-        //     }
-        //     Theme::Grineer => todo!(),
-        //     Theme::Lotus => {
-        //         test.hue.abs_diff_eq(&primary.hue, 5.0)
-        //             && test.saturation >= 0.65
-        //             && primary.lightness.abs_diff_eq(&test.lightness, 0.1)
-        //     }
-        //     Theme::Nidus => todo!(),
-        //     Theme::Orokin => todo!(),
-        //     Theme::Tenno => todo!(),
-        //     Theme::HighContrast => todo!(),
-        //     Theme::Legacy => todo!(),
-        //     Theme::Equinox => todo!(),
-        //     Theme::DarkLotus => todo!(),
-        //     Theme::Zephyr => todo!(),
-        // }
+        let equinox = themes.iter().find(|t| t.name == "Equinox").unwrap();
+        assert!(equinox.threshold_filter(Rgb([255, 255, 255])));
+        assert!(!equinox.threshold_filter(Rgb([255, 0, 0])));
+        let filter = equinox.filter.as_ref().unwrap();
+        assert!(filter.contains(Hsl::new(0.0, 0.2, 0.55)));
+        assert!(!filter.contains(Hsl::new(0.0, 0.21, 0.55)));
+
+        let label: Label =
+            serde_json::from_str(r#"{ "theme": "Stalker", "items": ["Kuva"] }"#).unwrap();
+        assert_eq!(label.theme.name, "Stalker");
+        assert!(serde_json::to_string(&label)
+            .unwrap()
+            .contains(r#""theme":"Stalker""#));
+        assert!(serde_json::from_str::<Label>(r#"{ "theme": "Nope", "items": [] }"#).is_err());
     }
 
-    pub fn primary(&self) -> Hsl {
-        let components = match self {
-            Theme::Vitruvian => (190, 169, 102),
-            Theme::Stalker => (153, 31, 35),
-            Theme::Baruuk => (238, 193, 105),
-            Theme::Corpus => (35, 201, 245),
-            Theme::Fortuna => (57, 105, 192),
-            Theme::Grineer => (255, 189, 102),
-            Theme::Lotus => (36, 184, 242),
-            Theme::Nidus => (140, 38, 92),
-            Theme::Orokin => (20, 41, 29),
-            Theme::Tenno => (9, 78, 106),
-            Theme::HighContrast => (2, 127, 217),
-            Theme::Legacy => (255, 255, 255),
-            Theme::Equinox => (158, 159, 167),
-            Theme::DarkLotus => (140, 119, 147),
-            Theme::Zephyr => (253, 132, 2),
-            Theme::Custom(range) => return range.get_average(),
-        };
+    #[test]
+    fn user_themes_override_built_in_ones() {
+        let mut themes: Vec<Theme> = serde_json::from_str(DEFAULT_THEMES).unwrap();
+        let overrides: Vec<Theme> = serde_json::from_str(
+            r#"[
+                { "name": "Lotus", "primary": [1, 2, 3], "secondary": [4, 5, 6], "tolerance": 0.1 },
+                { "name": "Kaleidoscope", "primary": [10, 20, 30], "secondary": [40, 50, 60] }
+            ]"#,
+        )
+        .unwrap();
+        merge_themes(&mut themes, overrides);
+        assert_eq!(themes.len(), 16);
+        let lotus = themes.iter().find(|t| t.name == "Lotus").unwrap();
+        assert_eq!(lotus.primary, [1, 2, 3]);
+        assert_eq!(lotus.tolerance, OrderedFloat(0.1));
+        let added = themes.last().unwrap();
+        assert_eq!(added.name, "Kaleidoscope");
+        assert_eq!(added.tolerance, default_tolerance());
+    }
 
-        let components = (
-            components.0 as f32 / 255.0,
-            components.1 as f32 / 255.0,
-            components.2 as f32 / 255.0,
+    #[test]
+    fn themes_are_ranked_by_weight() {
+        let themes: Vec<_> = ["Lotus", "Stalker", "Zephyr", "Corpus"]
+            .into_iter()
+            .map(|name| Theme::named(name).unwrap())
+            .collect();
+        let ranking = ThemeRanking::from_weights(themes.iter().zip([2.0, 5.0, 2.0, 1.0]));
+        let names: Vec<_> = ranking
+            .0
+            .iter()
+            .map(|(theme, _)| theme.name.as_str())
+            .collect();
+        assert_eq!(names, ["Stalker", "Lotus", "Zephyr", "Corpus"]);
+        assert_eq!(ranking.0[0].1, 0.5);
+        assert_eq!(ranking.0[3].1, 0.1);
+        assert_eq!(ranking.best().name, "Stalker");
+        assert_eq!(ranking.runner_up().unwrap().name, "Lotus");
+
+        let single = ThemeRanking::from_weights([(&themes[0], 3.0)]);
+        assert_eq!(single.runner_up(), None);
+        assert_eq!(single.into_best().name, "Lotus");
+    }
+
+    #[test]
+    fn histogram_ranking_matches_per_pixel_ranking() {
+        // A mostly dark background with some text colours and noise
+        let pixels: Vec<_> = (0..20_000u32)
+            .map(|index| {
+                let noise = (index.wrapping_mul(2_654_435_761) >> 8) as u8;
+                match index % 10 {
+                    0..=5 => Rgb([noise % 24, noise % 19, noise % 31]),
+                    6 | 7 => Rgb([36, 184, 242]),
+                    8 => Rgb([255, 241, 191]),
+                    _ => Rgb([noise, noise.wrapping_mul(7), noise.wrapping_mul(13)]),
+                }
+            })
+            .collect();
+
+        let mut weights: HashMap<&Theme, f32> = HashMap::new();
+        for pixel in &pixels {
+            let (theme, difference) = Theme::closest_from_color(*pixel);
+            *weights.entry(theme).or_insert(0.0) += 1.0 / (1.0 + difference).powi(4);
+        }
+        let per_pixel = ThemeRanking::from_weights(weights);
+
+        let histogram: ColorHistogram = pixels.iter().copied().collect();
+        assert!(histogram.len() < pixels.len() / 10);
+        let ranking = ThemeRanking::from_histogram(&histogram);
+
+        // Binned colours are compared by their average, so the scores are close but not exact
+        assert_eq!(ranking.best().name, "Lotus");
+        assert_eq!(ranking.0.len(), per_pixel.0.len());
+        for ((theme, score), (expected_theme, expected_score)) in ranking.0.iter().zip(&per_pixel.0)
+        {
+            assert_eq!(theme, expected_theme);
+            assert!((score - expected_score).abs() < 1e-3);
+        }
+
+        let shades: ColorHistogram = [Rgb([36, 184, 242]), Rgb([37, 185, 243])]
+            .into_iter()
+            .collect();
+        assert_eq!(shades.len(), 1);
+        assert_eq!(
+            shades.0.values().next().unwrap().average(),
+            Rgb([37, 185, 243])
         );
-        Hsl::from_color(Srgb::from_components(components))
+        for color in [[0, 0, 0], [36, 184, 242], [200, 10, 10], [158, 159, 167]] {
+            let (index, difference) = closest_reference(Rgb(color));
+            let (theme, expected_difference) = Theme::closest_from_color(Rgb(color));
+            assert_eq!(&THEMES[index], theme);
+            assert_eq!(difference, expected_difference);
+        }
     }
 
-    pub fn secondary(&self) -> Hsl {
-        let components = match self {
-            Theme::Vitruvian => (245, 227, 173),
-            Theme::Stalker => (255, 61, 51),
-            Theme::Baruuk => (236, 211, 162),
-            Theme::Corpus => (111, 229, 253),
-            Theme::Fortuna => (255, 115, 230),
-            Theme::Grineer => (255, 224, 153),
-            Theme::Lotus => (255, 241, 191),
-            Theme::Nidus => (245, 73, 93),
-            Theme::Orokin => (178, 125, 5),
-            Theme::Tenno => (6, 106, 74),
-            Theme::HighContrast => (255, 255, 0),
-            Theme::Legacy => (232, 213, 93),
-            Theme::Equinox => (232, 227, 227),
-            Theme::DarkLotus => (189, 169, 237),
-            Theme::Zephyr => (255, 53, 0),
-            Theme::Custom(range) => return range.get_average(),
+    #[test]
+    fn custom_themes_are_saved() {
+        let path = std::env::temp_dir().join(format!(
+            "wfinfo-test-custom-themes-{}.json",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        assert!(load_user_themes(&path).unwrap().is_empty());
+
+        let built_in: Vec<Theme> = serde_json::from_str(DEFAULT_THEMES).unwrap();
+        let built_in = |name: &str| {
+            built_in
+                .iter()
+                .find(|theme| theme.name == name)
+                .unwrap()
+                .clone()
+        };
+        let lotus = built_in("Lotus");
+        let filter = lotus.approximate_filter();
+        assert!(filter.contains(lotus.primary()) && filter.contains(lotus.secondary()));
+        let tuned = Theme {
+            name: "My Lotus".to_string(),
+            ..Theme::custom(filter.clone())
         };
+        save_user_theme(&path, tuned.clone()).unwrap();
+        save_user_theme(&path, built_in("Stalker")).unwrap();
+        save_user_theme(&path, tuned.clone()).unwrap();
+        assert_eq!(
+            load_user_themes(&path).unwrap(),
+            [tuned, built_in("Stalker")]
+        );
+        fs::remove_file(&path).unwrap();
 
-        let components = (
-            components.0 as f32 / 255.0,
-            components.1 as f32 / 255.0,
-            components.2 as f32 / 255.0,
+        let retuned = lotus.with_filter(filter.clone());
+        assert_eq!(retuned.filter, Some(filter));
+        assert_eq!(
+            (retuned.primary, retuned.secondary, retuned.tolerance),
+            (lotus.primary, lotus.secondary, lotus.tolerance)
         );
-        Hsl::from_color(Srgb::from_components(components))
+
+        assert_eq!("Stalker".parse::<Theme>().unwrap().name, "Stalker");
+        assert!("Nope".parse::<Theme>().is_err());
     }
 }
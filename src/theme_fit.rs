@@ -0,0 +1,237 @@
+use std::fmt;
+
+use image::DynamicImage;
+use ordered_float::OrderedFloat;
+
+use crate::{
+    database::{Database, Reward},
+    ocr::reward_image_to_reward_names,
+    testing::Label,
+    theme::{HslRange, Theme},
+};
+
+/// Step sizes of the search from coarse to fine: hue in degrees, then saturation and lightness.
+const FIT_STEPS: [(f32, f32); 3] = [(8.0, 0.08), (3.0, 0.03), (1.0, 0.01)];
+
+/// How many labeled reward slots were read as the labeled item.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Accuracy {
+    pub correct: usize,
+    pub total: usize,
+}
+
+impl Accuracy {
+    pub fn ratio(&self) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.correct as f32 / self.total as f32
+    }
+}
+
+impl fmt::Display for Accuracy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} ({:.1}%)",
+            self.correct,
+            self.total,
+            self.ratio() * 100.0
+        )
+    }
+}
+
+/// Scores the text read from one screenshot against its label. Labels name items by name or drop
+/// name, an empty label is a slot where nothing should be found.
+pub fn score_detections(database: &Database, detections: &[String], label: &Label) -> Accuracy {
+    let correct = label
+        .items
+        .iter()
+        .enumerate()
+        .filter(|(index, expected)| {
+            // Read the same way as the overlay, so e.g. "200 X Kuva" is scored as Kuva
+            let reward = detections
+                .get(*index)
+                .and_then(|text| database.find_reward(text));
+            match reward {
+                Some(Reward { item, .. }) => {
+                    item.name == **expected || item.drop_name == **expected
+                }
+                None => expected.is_empty(),
+            }
+        })
+        .count();
+    Accuracy {
+        correct,
+        total: label.items.len(),
+    }
+}
+
+/// Reads every screenshot with the theme and scores it against its label.
+pub fn theme_accuracy(
+    database: &Database,
+    screenshots: &[(DynamicImage, Label)],
+    theme: &Theme,
+) -> Accuracy {
+    screenshots
+        .iter()
+        .map(|(image, label)| {
+            let detections = reward_image_to_reward_names(image.clone(), Some(theme.clone()));
+            score_detections(database, &detections, label)
+        })
+        .fold(Accuracy::default(), |sum, accuracy| Accuracy {
+            correct: sum.correct + accuracy.correct,
+            total: sum.total + accuracy.total,
+        })
+}
+
+/// Searches the range with the highest score, starting from `start`. One end of one range is
+/// moved at a time while that improves the score, with ever smaller steps. Stops after
+/// `max_evaluations` calls of `score`, returning the best range and its score.
+pub fn fit_range(
+    start: HslRange<OrderedFloat<f32>>,
+    max_evaluations: usize,
+    mut score: impl FnMut(&HslRange<OrderedFloat<f32>>) -> f32,
+) -> (HslRange<OrderedFloat<f32>>, f32) {
+    let mut best = start;
+    let mut best_score = score(&best);
+    let mut evaluations = 1;
+
+    for (hue_step, step) in FIT_STEPS {
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for (end, delta) in [
+                (0, -hue_step),
+                (0, hue_step),
+                (1, -hue_step),
+                (1, hue_step),
+                (2, -step),
+                (2, step),
+                (3, -step),
+                (3, step),
+                (4, -step),
+                (4, step),
+                (5, -step),
+                (5, step),
+            ] {
+                if evaluations >= max_evaluations {
+                    return (best, best_score);
+                }
+                let Some(candidate) = moved_end(&best, end, delta) else {
+                    continue;
+                };
+                let candidate_score = score(&candidate);
+                evaluations += 1;
+                if candidate_score > best_score {
+                    best = candidate;
+                    best_score = candidate_score;
+                    improved = true;
+                }
+            }
+        }
+    }
+    (best, best_score)
+}
+
+/// Moves one end of a range: the start and end of the hue, saturation and lightness in that
+/// order. `None` when the range would be empty or is already at its bound.
+fn moved_end(
+    range: &HslRange<OrderedFloat<f32>>,
+    end: usize,
+    delta: f32,
+) -> Option<HslRange<OrderedFloat<f32>>> {
+    let mut moved = range.clone();
+    let (component, (min, max)) = match end / 2 {
+        0 => (&mut moved.hue, (-180.0, 181.0)),
+        1 => (&mut moved.saturation, (0.0, 1.01)),
+        _ => (&mut moved.lightness, (0.0, 1.01)),
+    };
+    let value = match end % 2 {
+        0 => &mut component.start,
+        _ => &mut component.end,
+    };
+    let new = (value.0 + delta).clamp(min, max);
+    if new == value.0 {
+        return None;
+    }
+    *value = OrderedFloat(new);
+    (component.start < component.end).then_some(moved)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn range(values: [f32; 6]) -> HslRange<OrderedFloat<f32>> {
+        HslRange {
+            hue: values[0]..values[1],
+            saturation: values[2]..values[3],
+            lightness: values[4]..values[5],
+        }
+        .to_ordered()
+    }
+
+    #[test]
+    fn detections_are_scored_against_labels() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let label = Label {
+            theme: Theme::named("Lotus").unwrap(),
+            items: vec![
+                "Titania Prime Systems Blueprint".to_string(),
+                "Burston Prime Stock".to_string(),
+                "Forma Blueprint".to_string(),
+                "Kuva".to_string(),
+                "".to_string(),
+            ],
+        };
+        let detections: Vec<_> = [
+            "Titania Prime Systems\nBlueprint",
+            "Burston Prime Stock",
+            "Kuva",
+            "200 X Kuva",
+            "",
+        ]
+        .into_iter()
+        .map(str::to_owned)
+        .collect();
+        let accuracy = score_detections(&database, &detections, &label);
+        assert_eq!(
+            accuracy,
+            Accuracy {
+                correct: 4,
+                total: 5
+            }
+        );
+        assert_eq!(accuracy.to_string(), "4/5 (80.0%)");
+    }
+
+    #[test]
+    fn fitting_finds_the_best_range() {
+        let target = [-5.0, 12.0, 0.5, 1.0, 0.25, 0.65];
+        let distance = |range: &HslRange<OrderedFloat<f32>>| {
+            let range = range.to_unordered();
+            let values = [
+                range.hue.start,
+                range.hue.end,
+                range.saturation.start,
+                range.saturation.end,
+                range.lightness.start,
+                range.lightness.end,
+            ];
+            // Hue is in degrees
+            let scales = [180.0, 180.0, 1.0, 1.0, 1.0, 1.0];
+            (0..6).fold(0.0, |sum, index| {
+                sum + (values[index] - target[index]).abs() / scales[index]
+            })
+        };
+        let start = range([-20.0, 20.0, 0.3, 0.9, 0.2, 0.8]);
+
+        let (fitted, score) = fit_range(start.clone(), 1000, |range| -distance(range));
+        assert!(distance(&fitted) < 0.05, "{fitted:?}");
+        assert_eq!(score, -distance(&fitted));
+
+        let (limited, _) = fit_range(start.clone(), 1, |range| -distance(range));
+        assert_eq!(limited, start);
+    }
+}
@@ -0,0 +1,220 @@
+use anyhow::anyhow;
+use serde::Serialize;
+
+use crate::{
+    database::{Database, Valuation},
+    inventory::OwnedRelic,
+    wfinfo_data::item_data::{Refinement, Relic, RelicId},
+};
+
+/// Refinement costs are all multiples of this many void traces.
+const TRACE_STEP: u32 = 25;
+
+/// Value gained by refining one copy of a relic to a higher refinement.
+#[derive(Clone, Debug, Serialize)]
+pub struct RefinementOption {
+    pub from: Refinement,
+    pub to: Refinement,
+    pub traces: u32,
+    pub gain: f32,
+}
+
+impl RefinementOption {
+    pub fn gain_per_trace(&self) -> f32 {
+        self.gain / self.traces as f32
+    }
+}
+
+/// Copies of an owned relic stack that should be refined.
+#[derive(Clone, Debug, Serialize)]
+pub struct Upgrade {
+    #[serde(flatten)]
+    pub relic: RelicId,
+    pub from: Refinement,
+    pub to: Refinement,
+    pub count: u32,
+    pub traces: u32,
+    pub gain: f32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TracePlan {
+    pub upgrades: Vec<Upgrade>,
+    pub traces_spent: u32,
+    pub total_gain: f32,
+}
+
+/// Lists the gain of refining one copy of the relic from `from` to every higher refinement,
+/// assuming the whole squad cracks the relic at the same refinement.
+pub fn refinement_options(
+    database: &Database,
+    relic: &Relic,
+    from: Refinement,
+    number_of_relics: u32,
+    valuation: Valuation,
+) -> Vec<RefinementOption> {
+    let base = database.shared_relic_value_in(relic, from, number_of_relics, valuation);
+    Refinement::ALL
+        .into_iter()
+        .filter(|&to| to > from)
+        .map(|to| RefinementOption {
+            from,
+            to,
+            traces: to.trace_cost() - from.trace_cost(),
+            gain: database.shared_relic_value_in(relic, to, number_of_relics, valuation) - base,
+        })
+        .collect()
+}
+
+/// Picks the refinements for the owned relics that maximise the total expected gain without
+/// spending more than `trace_balance` void traces.
+pub fn plan_trace_spending(
+    database: &Database,
+    owned: &[OwnedRelic],
+    trace_balance: u32,
+    number_of_relics: u32,
+    valuation: Valuation,
+) -> Result<TracePlan, anyhow::Error> {
+    let mut copies = Vec::new();
+    for (stack_index, stack) in owned.iter().enumerate() {
+        let relic = database
+            .relics
+            .get(&stack.relic)
+            .ok_or_else(|| anyhow!("Unknown relic: {}", stack.relic))?;
+        let options: Vec<_> = refinement_options(
+            database,
+            relic,
+            stack.refinement,
+            number_of_relics,
+            valuation,
+        )
+        .into_iter()
+        .filter(|option| option.gain > 0.0)
+        .collect();
+        for _ in 0..stack.count {
+            copies.push((stack_index, options.clone()));
+        }
+    }
+
+    // Multiple choice knapsack over the budget in steps of 25 traces. best[units] is the highest
+    // gain reachable with at most that many steps spent.
+    let budget = (trace_balance / TRACE_STEP) as usize;
+    let mut best = vec![0.0_f32; budget + 1];
+    let mut choices = Vec::with_capacity(copies.len());
+    for (_, options) in copies.iter() {
+        let mut next = best.clone();
+        let mut choice = vec![None; budget + 1];
+        for units in 0..=budget {
+            for (option_index, option) in options.iter().enumerate() {
+                let cost = (option.traces / TRACE_STEP) as usize;
+                if cost <= units && best[units - cost] + option.gain > next[units] {
+                    next[units] = best[units - cost] + option.gain;
+                    choice[units] = Some(option_index);
+                }
+            }
+        }
+        best = next;
+        choices.push(choice);
+    }
+
+    let mut upgrades: Vec<Upgrade> = Vec::new();
+    let mut units = budget;
+    for ((stack_index, options), choice) in copies.iter().zip(choices.iter()).rev() {
+        let Some(option_index) = choice[units] else {
+            continue;
+        };
+        let option = &options[option_index];
+        units -= (option.traces / TRACE_STEP) as usize;
+
+        let stack = &owned[*stack_index];
+        match upgrades.iter_mut().find(|upgrade| {
+            upgrade.relic == stack.relic && upgrade.from == option.from && upgrade.to == option.to
+        }) {
+            Some(upgrade) => {
+                upgrade.count += 1;
+                upgrade.traces += option.traces;
+                upgrade.gain += option.gain;
+            }
+            None => upgrades.push(Upgrade {
+                relic: stack.relic.clone(),
+                from: option.from,
+                to: option.to,
+                count: 1,
+                traces: option.traces,
+                gain: option.gain,
+            }),
+        }
+    }
+    upgrades.sort_by(|a, b| b.gain.total_cmp(&a.gain));
+
+    Ok(TracePlan {
+        traces_spent: upgrades.iter().map(|upgrade| upgrade.traces).sum(),
+        total_gain: upgrades
+            .iter()
+            .fold(0.0, |total, upgrade| total + upgrade.gain),
+        upgrades,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use approx::assert_relative_eq;
+
+    use super::*;
+    use crate::wfinfo_data::item_data::RelicTier;
+
+    fn owned(database: &Database, count: u32) -> Vec<OwnedRelic> {
+        database
+            .relics
+            .tier(RelicTier::Lith)
+            .keys()
+            .take(2)
+            .map(|name| OwnedRelic {
+                relic: RelicId::new(RelicTier::Lith, name),
+                refinement: Refinement::Intact,
+                count,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty_balance_refines_nothing() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let plan = plan_trace_spending(&database, &owned(&database, 3), 20, 4, Valuation::Platinum)
+            .unwrap();
+        assert!(plan.upgrades.is_empty());
+        assert_eq!(plan.traces_spent, 0);
+    }
+
+    #[test]
+    fn plan_matches_bruteforce() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let owned = owned(&database, 1);
+        let options: Vec<Vec<RefinementOption>> = owned
+            .iter()
+            .map(|stack| {
+                let relic = database.relics.get(&stack.relic).unwrap();
+                refinement_options(&database, relic, stack.refinement, 4, Valuation::Platinum)
+            })
+            .collect();
+
+        for balance in [0, 25, 75, 100, 150, 200] {
+            let plan =
+                plan_trace_spending(&database, &owned, balance, 4, Valuation::Platinum).unwrap();
+            assert!(plan.traces_spent <= balance);
+
+            let mut best: f32 = 0.0;
+            for first in 0..=options[0].len() {
+                for second in 0..=options[1].len() {
+                    let picked = [options[0].get(first), options[1].get(second)];
+                    let traces: u32 = picked.iter().flatten().map(|option| option.traces).sum();
+                    let gain: f32 = picked.iter().flatten().map(|option| option.gain).sum();
+                    if traces <= balance {
+                        best = best.max(gain);
+                    }
+                }
+            }
+            assert_relative_eq!(plan.total_gain, best, epsilon = 0.01);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use serde::Serialize;
+
+use crate::database::Database;
+
+/// Value of an item or set mentioned in a trade message.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PriceCheck {
+    /// The name as written in the message.
+    pub mention: String,
+    pub name: String,
+    pub platinum: f32,
+    /// `None` for sets, Baro Ki'Teer only takes parts.
+    pub ducats: Option<usize>,
+    pub vaulted: bool,
+}
+
+/// Words of a trade message that are not part of an item name, like "WTS", "45p" or "x2".
+fn is_trade_noise(word: &str) -> bool {
+    let word = word
+        .trim_matches(|c: char| !c.is_ascii_alphanumeric())
+        .to_lowercase();
+    let number = |text: &str| text.chars().all(|c| c.is_ascii_digit());
+    matches!(
+        word.as_str(),
+        "wts" | "wtb" | "wtt" | "pm" | "me" | "selling" | "buying" | "for" | "each" | "plat"
+    ) || number(word.trim_end_matches('p'))
+        || word.strip_prefix('x').is_some_and(number)
+}
+
+/// The item names in a trade message. Chat links like "[Octavia Prime Systems]" are used when
+/// there are any, otherwise the message is split at commas and similar separators.
+pub fn item_mentions(message: &str) -> Vec<String> {
+    let links: Vec<_> = message
+        .split('[')
+        .skip(1)
+        .filter_map(|link| link.split_once(']'))
+        .map(|(name, _)| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if !links.is_empty() {
+        return links;
+    }
+
+    message
+        .split([',', '/', '|', ';', '\n'])
+        .map(|chunk| {
+            chunk
+                .split_whitespace()
+                .filter(|word| !is_trade_noise(word))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Capitalises every word, as item names are matched case sensitively when fuzzy.
+fn title_case(name: &str) -> String {
+    name.split_whitespace()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Looks up a mentioned name as a set, or else as an item matched like
+/// [`Database::lookup_item`].
+pub fn price_check_name(database: &Database, mention: &str) -> Option<PriceCheck> {
+    if let Some(set) = database.find_set(mention) {
+        return Some(PriceCheck {
+            mention: mention.to_owned(),
+            name: set.name.clone(),
+            platinum: set.platinum,
+            ducats: None,
+            vaulted: set.vaulted,
+        });
+    }
+    let item = database.lookup_item(&title_case(mention))?;
+    Some(PriceCheck {
+        mention: mention.to_owned(),
+        name: item.name.clone(),
+        platinum: item.platinum,
+        ducats: Some(item.ducats),
+        vaulted: item.vaulted,
+    })
+}
+
+/// Values every recognised item or set mentioned in a trade message.
+pub fn price_check(database: &Database, message: &str) -> Vec<PriceCheck> {
+    item_mentions(message)
+        .iter()
+        .filter_map(|mention| price_check_name(database, mention))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_find_item_mentions() {
+        assert_eq!(
+            item_mentions("WTS [Octavia Prime Systems] 45p [Burston Prime Stock]x2 PM me"),
+            ["Octavia Prime Systems", "Burston Prime Stock"]
+        );
+        assert_eq!(
+            item_mentions("WTS octavia prime systems 45p, burston prime stock x2 10p each"),
+            ["octavia prime systems", "burston prime stock"]
+        );
+        assert!(item_mentions("WTB 45p").is_empty());
+    }
+
+    #[test]
+    fn can_price_check_messages() {
+        let database = Database::load_from_file(None, None).unwrap();
+        let checks = price_check(
+            &database,
+            "WTS titania prime systems blueprint 20p, Titania Prime Set / Not An Item",
+        );
+        let names: Vec<_> = checks.iter().map(|check| check.name.as_str()).collect();
+        assert_eq!(names, ["Titania Prime Systems", "Titania Prime Set"]);
+        assert!(checks[0].ducats.is_some());
+        assert_eq!(checks[1].ducats, None);
+    }
+}
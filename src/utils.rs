@@ -1,20 +1,122 @@
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Args;
+use log::warn;
+use serde::Deserialize;
+
+use crate::{database::Database, history::PriceHistory, theme::Theme};
+
+/// Command line options selecting where the price and item data is loaded from, shared by all
+/// binaries.
+#[derive(Args, Clone, Debug)]
+pub struct DataArguments {
+    /// Directory containing prices.json and filtered_items.json, as written by update.sh.
+    /// Without it the files are downloaded and cached in the temporary directory.
+    #[arg(long, global = true)]
+    pub data_dir: Option<PathBuf>,
+    /// Download the data files again even if a cached copy exists
+    #[arg(long, global = true, conflicts_with = "data_dir")]
+    pub refresh: bool,
+}
+
+impl DataArguments {
+    /// Paths of prices.json and filtered_items.json, downloading them if needed.
+    pub fn data_files(&self) -> Result<(PathBuf, PathBuf), anyhow::Error> {
+        let (prices, items) = match &self.data_dir {
+            Some(directory) => (
+                directory.join("prices.json"),
+                directory.join("filtered_items.json"),
+            ),
+            None => fetch_prices_and_items_with_refresh(self.refresh)?,
+        };
+        Ok((prices, items))
+    }
+
+    pub fn load_database(&self) -> Result<Database, anyhow::Error> {
+        let (prices, items) = self.data_files()?;
+        Database::load_from_file(Some(&prices), Some(&items))
+    }
+}
+
+/// Directory for files kept between runs: $XDG_DATA_HOME/wfinfo-ng, falling back to
+/// ~/.local/share/wfinfo-ng.
+pub fn data_directory() -> PathBuf {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .unwrap_or_else(std::env::temp_dir);
+    data_home.join("wfinfo-ng")
+}
+
+/// Settings from config.json in the data directory, e.g. `{ "theme": "Lotus" }`. Command line
+/// arguments take precedence over them.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Name of the UI theme to read screenshots with instead of detecting it, like `--theme`
+    pub theme: Option<String>,
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        data_directory().join("config.json")
+    }
+
+    /// Reads the config file, a missing file leaves every setting at its default.
+    pub fn load(path: &Path) -> Result<Config, anyhow::Error> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Invalid config in {}", path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// The configured theme, looked up in the loaded themes.
+    pub fn theme(&self) -> Result<Option<Theme>, anyhow::Error> {
+        self.theme.as_deref().map(str::parse).transpose()
+    }
+}
 
 pub fn fetch_prices_and_items() -> Result<(PathBuf, PathBuf), anyhow::Error> {
-    let prices = download_and_save("https://api.warframestat.us/wfinfo/prices/", "prices.json")?;
-    let items = download_and_save(
+    fetch_prices_and_items_with_refresh(false)
+}
+
+pub fn fetch_prices_and_items_with_refresh(
+    refresh: bool,
+) -> Result<(PathBuf, PathBuf), anyhow::Error> {
+    let (prices, downloaded) = download_and_save(
+        "https://api.warframestat.us/wfinfo/prices/",
+        "prices.json",
+        refresh,
+    )?;
+    // Keep a dated copy of every price list downloaded, for `relics history`
+    if downloaded {
+        if let Err(err) = PriceHistory::default().record(&prices) {
+            warn!("Failed to keep a dated copy of {}: {err}", prices.display());
+        }
+    }
+    let (items, _) = download_and_save(
         "https://api.warframestat.us/wfinfo/filtered_items/",
         "filtered_items.json",
+        refresh,
     )?;
     Ok((prices, items))
 }
 
-fn download_and_save(url: &str, filename: &str) -> Result<PathBuf, anyhow::Error> {
+/// Downloads a file into the temporary directory unless it is cached there already, returning
+/// its path and whether it was downloaded.
+fn download_and_save(
+    url: &str,
+    filename: &str,
+    refresh: bool,
+) -> Result<(PathBuf, bool), anyhow::Error> {
     let path = std::env::temp_dir().join(filename);
-    if path.exists() {
-        return Ok(path);
+    if path.exists() && !refresh {
+        return Ok((path, false));
     }
 
     let res = reqwest::blocking::get(url)?;
@@ -25,5 +127,29 @@ fn download_and_save(url: &str, filename: &str) -> Result<PathBuf, anyhow::Error
         .open(&path)?;
     file.write_all(res.text()?.as_bytes())?;
 
-    Ok(path)
+    Ok((path, true))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config_is_loaded() {
+        let path =
+            std::env::temp_dir().join(format!("wfinfo-test-config-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert!(Config::load(&path).unwrap().theme().unwrap().is_none());
+
+        fs::write(&path, r#"{ "theme": "Stalker" }"#).unwrap();
+        let theme = Config::load(&path).unwrap().theme().unwrap().unwrap();
+        assert_eq!(theme.name, "Stalker");
+
+        fs::write(&path, r#"{ "theme": "Nonexistent" }"#).unwrap();
+        assert!(Config::load(&path).unwrap().theme().is_err());
+        fs::write(&path, "{ theme: Stalker }").unwrap();
+        assert!(Config::load(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
 }
@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_aux::prelude::deserialize_number_from_string;
 
 pub mod price_data {
@@ -13,7 +13,15 @@ pub mod price_data {
 }
 
 pub mod item_data {
-    use std::collections::HashMap;
+    use std::{
+        collections::{BTreeMap, HashMap},
+        fmt,
+        str::FromStr,
+    };
+
+    use anyhow::{anyhow, bail, Context};
+    use levenshtein::levenshtein;
+    use serde_json::Value;
 
     use super::*;
 
@@ -21,6 +29,13 @@ pub mod item_data {
     pub struct DucatItem {
         #[serde(default)]
         pub ducats: usize,
+        /// How many of this part one set needs, e.g. 2 barrels for dual pistols.
+        #[serde(default = "one")]
+        pub count: u32,
+    }
+
+    fn one() -> u32 {
+        1
     }
 
     #[derive(Clone, Debug, Deserialize)]
@@ -44,7 +59,14 @@ pub mod item_data {
         pub parts: HashMap<String, DucatItem>,
     }
 
-    #[derive(Copy, Clone, Debug)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+    pub enum Rarity {
+        Common,
+        Uncommon,
+        Rare,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
     pub enum Refinement {
         Intact,
         Exceptional,
@@ -52,15 +74,74 @@ pub mod item_data {
         Radiant,
     }
 
+    /// A reward of a relic. `chances` replaces the usual chance of its rarity at the listed
+    /// refinements, for relics that don't follow the common layout.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct RelicDrop {
+        pub item: String,
+        pub rarity: Rarity,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        pub chances: HashMap<Refinement, f32>,
+    }
+
+    /// A relic and its rewards, ordered from common to rare.
     #[derive(Clone, Debug, Deserialize)]
+    #[serde(try_from = "RelicData")]
     pub struct Relic {
         pub vaulted: bool,
-        pub rare1: String,
-        pub uncommon1: String,
-        pub uncommon2: String,
-        pub common1: String,
-        pub common2: String,
-        pub common3: String,
+        pub rewards: Vec<RelicDrop>,
+    }
+
+    /// Relics as stored in filtered_items.json, either with an explicit list of rewards or with
+    /// one field per reward slot like "rare1" and "common3".
+    #[derive(Deserialize)]
+    struct RelicData {
+        vaulted: bool,
+        #[serde(default)]
+        rewards: Vec<RelicDrop>,
+        #[serde(flatten)]
+        slots: BTreeMap<String, Value>,
+    }
+
+    impl TryFrom<RelicData> for Relic {
+        type Error = anyhow::Error;
+
+        fn try_from(data: RelicData) -> Result<Self, Self::Error> {
+            let mut rewards = data.rewards;
+            for (slot, item) in data.slots {
+                let rarity = match slot.trim_end_matches(|c: char| c.is_ascii_digit()) {
+                    "common" => Rarity::Common,
+                    "uncommon" => Rarity::Uncommon,
+                    "rare" => Rarity::Rare,
+                    _ => continue,
+                };
+                let Value::String(item) = item else {
+                    bail!("Expected an item name in relic slot {slot}");
+                };
+                rewards.push(RelicDrop {
+                    item,
+                    rarity,
+                    chances: HashMap::new(),
+                });
+            }
+            if rewards.is_empty() || rewards.iter().any(|reward| reward.item.is_empty()) {
+                bail!("Relic has no rewards");
+            }
+            rewards.sort_by_key(|reward| reward.rarity);
+
+            Ok(Relic {
+                vaulted: data.vaulted,
+                rewards,
+            })
+        }
+    }
+
+    impl Relic {
+        pub fn drops(&self) -> impl Iterator<Item = (&String, Rarity)> {
+            self.rewards
+                .iter()
+                .map(|reward| (&reward.item, reward.rarity))
+        }
     }
 
     #[derive(Clone, Debug, Deserialize)]
@@ -75,6 +156,259 @@ pub mod item_data {
         pub axi: HashMap<String, Relic>,
     }
 
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+    pub enum RelicTier {
+        Lith,
+        Meso,
+        Neo,
+        Axi,
+    }
+
+    impl RelicTier {
+        pub const ALL: [RelicTier; 4] = [
+            RelicTier::Lith,
+            RelicTier::Meso,
+            RelicTier::Neo,
+            RelicTier::Axi,
+        ];
+    }
+
+    impl fmt::Display for RelicTier {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            fmt::Debug::fmt(self, f)
+        }
+    }
+
+    impl FromStr for RelicTier {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            RelicTier::ALL
+                .into_iter()
+                .find(|tier| tier.to_string().eq_ignore_ascii_case(s.trim()))
+                .ok_or_else(|| anyhow!("Invalid relic tier: {s}"))
+        }
+    }
+
+    /// Identifies a relic, e.g. Axi A1.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+    pub struct RelicId {
+        pub tier: RelicTier,
+        pub name: String,
+    }
+
+    impl RelicId {
+        pub fn new(tier: RelicTier, name: &str) -> Self {
+            RelicId {
+                tier,
+                name: name.to_owned(),
+            }
+        }
+
+        /// Matches OCR output such as "AXl A1 RELIC" or "Axi A1 Relic [Radiant]" against the
+        /// known relics. `None` unless a single relic is the closest match, so a misread is not
+        /// taken for a neighbouring relic.
+        pub fn from_ocr(text: &str, relics: &Relics) -> Option<RelicId> {
+            // The refinement follows the name in brackets and is often misread, e.g. "[FLAWLES5]"
+            let name = text.split(['[', '(']).next().unwrap_or_default();
+            let mut text: String = name
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_uppercase();
+            for noise in ["RELIC", "INTACT", "EXCEPTIONAL", "FLAWLESS", "RADIANT"] {
+                text = text.replace(noise, "");
+            }
+
+            let mut best: Option<(usize, RelicId)> = None;
+            let mut tied = false;
+            for id in relics.ids() {
+                let candidate = format!("{}{}", id.tier, id.name).to_uppercase();
+                let distance = levenshtein(&candidate, &text);
+                match &best {
+                    Some((best_distance, _)) if distance > *best_distance => {}
+                    Some((best_distance, _)) if distance == *best_distance => tied = true,
+                    _ => {
+                        best = Some((distance, id));
+                        tied = false;
+                    }
+                }
+            }
+            let (distance, id) = best?;
+            (distance <= 2 && !tied).then_some(id)
+        }
+    }
+
+    impl fmt::Display for RelicId {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} {}", self.tier, self.name)
+        }
+    }
+
+    /// Parses relics written as "Axi A1", ignoring case and a trailing "Relic".
+    impl FromStr for RelicId {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let mut words = s.split_whitespace();
+            let (Some(tier), Some(name)) = (words.next(), words.next()) else {
+                bail!("Expected a relic like \"Axi A1\", got: {s}");
+            };
+            match words.next() {
+                None => {}
+                Some(word) if word.eq_ignore_ascii_case("relic") && words.next().is_none() => {}
+                Some(_) => bail!("Expected a relic like \"Axi A1\", got: {s}"),
+            }
+            Ok(RelicId {
+                tier: tier.parse()?,
+                name: name.to_uppercase(),
+            })
+        }
+    }
+
+    impl Relics {
+        pub fn tier(&self, tier: RelicTier) -> &HashMap<String, Relic> {
+            match tier {
+                RelicTier::Lith => &self.lith,
+                RelicTier::Meso => &self.meso,
+                RelicTier::Neo => &self.neo,
+                RelicTier::Axi => &self.axi,
+            }
+        }
+
+        pub fn get(&self, id: &RelicId) -> Option<&Relic> {
+            self.tier(id.tier).get(&id.name)
+        }
+
+        pub fn get_mut(&mut self, id: &RelicId) -> Option<&mut Relic> {
+            self.tier_mut(id.tier).get_mut(&id.name)
+        }
+
+        fn tier_mut(&mut self, tier: RelicTier) -> &mut HashMap<String, Relic> {
+            match tier {
+                RelicTier::Lith => &mut self.lith,
+                RelicTier::Meso => &mut self.meso,
+                RelicTier::Neo => &mut self.neo,
+                RelicTier::Axi => &mut self.axi,
+            }
+        }
+
+        /// Keeps only the relics for which `keep` returns true.
+        pub fn retain(&mut self, mut keep: impl FnMut(&RelicId, &Relic) -> bool) {
+            for tier in RelicTier::ALL {
+                self.tier_mut(tier)
+                    .retain(|name, relic| keep(&RelicId::new(tier, name), relic));
+            }
+        }
+
+        /// Iterates over every relic with its id, tier by tier.
+        pub fn iter(&self) -> impl Iterator<Item = (RelicId, &Relic)> {
+            RelicTier::ALL.into_iter().flat_map(move |tier| {
+                self.tier(tier)
+                    .iter()
+                    .map(move |(name, relic)| (RelicId::new(tier, name), relic))
+            })
+        }
+
+        pub fn ids(&self) -> impl Iterator<Item = RelicId> + '_ {
+            self.iter().map(|(id, _)| id)
+        }
+    }
+
+    /// Chance of each reward of a relic by its rarity, for every refinement. Loaded from
+    /// drop_chances.json next to filtered_items.json, e.g. {"Intact": {"Common": 0.2533, ..}}.
+    /// Relics with a different layout list the chances of their rewards under "relics", e.g.
+    /// {"relics": {"Axi A1": {"Forma Blueprint": {"Intact": 0.5}}}}.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct DropChances {
+        #[serde(flatten)]
+        chances: HashMap<Refinement, HashMap<Rarity, f32>>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        relics: BTreeMap<String, BTreeMap<String, HashMap<Refinement, f32>>>,
+    }
+
+    impl Default for DropChances {
+        fn default() -> Self {
+            let chances = Refinement::ALL
+                .into_iter()
+                .map(|refinement| {
+                    let by_rarity = [Rarity::Common, Rarity::Uncommon, Rarity::Rare]
+                        .into_iter()
+                        .map(|rarity| (rarity, refinement.chance(rarity)))
+                        .collect();
+                    (refinement, by_rarity)
+                })
+                .collect();
+            DropChances {
+                chances,
+                relics: BTreeMap::new(),
+            }
+        }
+    }
+
+    impl DropChances {
+        /// Chance of a reward of the rarity, the built-in one when the file doesn't list it.
+        pub fn chance(&self, refinement: Refinement, rarity: Rarity) -> f32 {
+            self.chances
+                .get(&refinement)
+                .and_then(|by_rarity| by_rarity.get(&rarity))
+                .copied()
+                .unwrap_or_else(|| refinement.chance(rarity))
+        }
+
+        /// Chance of one reward, preferring the reward's own chance at this refinement over the
+        /// chance of its rarity.
+        pub fn drop_chance(&self, drop: &RelicDrop, refinement: Refinement) -> f32 {
+            drop.chances
+                .get(&refinement)
+                .copied()
+                .unwrap_or_else(|| self.chance(refinement, drop.rarity))
+        }
+
+        /// Copies the chances listed under "relics" onto the rewards of those relics.
+        pub fn apply_overrides(&self, relics: &mut Relics) -> Result<(), anyhow::Error> {
+            for (id, items) in &self.relics {
+                let id: RelicId = id.parse()?;
+                let relic = relics
+                    .get_mut(&id)
+                    .ok_or_else(|| anyhow!("Drop chances for unknown relic {id}"))?;
+                for (item, chances) in items {
+                    let drop = relic
+                        .rewards
+                        .iter_mut()
+                        .find(|drop| drop.item == *item)
+                        .ok_or_else(|| {
+                            anyhow!("Drop chances for {item}, which {id} doesn't drop")
+                        })?;
+                    drop.chances.extend(chances);
+                }
+            }
+            Ok(())
+        }
+
+        /// Checks that the rewards of a relic add up to certainty at every refinement.
+        pub fn validate_relic(&self, relic: &Relic) -> Result<(), anyhow::Error> {
+            for refinement in Refinement::ALL {
+                let total = relic.rewards.iter().fold(0.0, |total, drop| {
+                    total + self.drop_chance(drop, refinement)
+                });
+                if (total - 1.0).abs() > 0.001 {
+                    bail!("Drop chances at {refinement:?} add up to {total}");
+                }
+            }
+            Ok(())
+        }
+
+        /// Checks every relic, see [`DropChances::validate_relic`].
+        pub fn validate(&self, relics: &Relics) -> Result<(), anyhow::Error> {
+            for (id, relic) in relics.iter() {
+                self.validate_relic(relic)
+                    .with_context(|| format!("Invalid relic {id}"))?;
+            }
+            Ok(())
+        }
+    }
+
     #[derive(Clone, Debug, Deserialize)]
     pub struct FilteredItems {
         pub errors: Vec<String>,
@@ -84,6 +418,14 @@ pub mod item_data {
     }
 
     impl Refinement {
+        pub const ALL: [Refinement; 4] = [
+            Refinement::Intact,
+            Refinement::Exceptional,
+            Refinement::Flawless,
+            Refinement::Radiant,
+        ];
+
+        /// Default chance of each common reward, used without a drop_chances.json.
         pub fn common_chance(&self) -> f32 {
             match self {
                 Refinement::Intact => 0.2533,
@@ -110,5 +452,51 @@ pub mod item_data {
                 Refinement::Radiant => 0.1,
             }
         }
+
+        /// Reads the refinement shown after a relic name like "[RADIANT]", intact relics show
+        /// none.
+        pub fn from_ocr(text: &str) -> Refinement {
+            text.to_uppercase()
+                .split(|c: char| !c.is_ascii_alphabetic())
+                .filter(|word| word.len() >= 5)
+                .find_map(|word| {
+                    Refinement::ALL.into_iter().find(|refinement| {
+                        levenshtein(&format!("{refinement:?}").to_uppercase(), word) <= 2
+                    })
+                })
+                .unwrap_or(Refinement::Intact)
+        }
+
+        /// Void traces needed to refine an intact relic to this refinement.
+        pub fn trace_cost(&self) -> u32 {
+            match self {
+                Refinement::Intact => 0,
+                Refinement::Exceptional => 25,
+                Refinement::Flawless => 50,
+                Refinement::Radiant => 100,
+            }
+        }
+
+        pub fn chance(&self, rarity: Rarity) -> f32 {
+            match rarity {
+                Rarity::Common => self.common_chance(),
+                Rarity::Uncommon => self.uncommon_chance(),
+                Rarity::Rare => self.rare_chance(),
+            }
+        }
+    }
+
+    impl FromStr for Refinement {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_lowercase().as_str() {
+                "intact" => Ok(Refinement::Intact),
+                "exceptional" => Ok(Refinement::Exceptional),
+                "flawless" => Ok(Refinement::Flawless),
+                "radiant" => Ok(Refinement::Radiant),
+                _ => Err(anyhow!("Invalid refinement: {s}")),
+            }
+        }
     }
 }